@@ -1,6 +1,10 @@
 #[macro_use]
 extern crate bencher;
 use std::thread::{self, JoinHandle};
+use tiny_redis::connection::inbound::CommandIter;
+use tiny_redis::connection::mock_tcp_stream::MockTcpStream;
+use tiny_redis::connection::outbound;
+use tiny_redis::core::{Command, Core, Key};
 use tiny_redis::job_queue::{channel_queue, deque, disruptor, JobQueue};
 
 use bencher::Bencher;
@@ -130,6 +134,98 @@ fn disruptor_single_thread_dequeue(bench: &mut Bencher) {
     })
 }
 
+fn mset_10k_pairs() -> String {
+    let mut input = String::from("*20001\r\n$4\r\nMSET\r\n");
+    for i in 0..10_000 {
+        let key = format!("key:{i:05}");
+        input += &format!("${}\r\n{}\r\n$5\r\nvalue\r\n", key.len(), key);
+    }
+    input
+}
+
+/// Benchmark parsing a command with a lot of arguments
+fn parse_mset_10k_pairs(bench: &mut Bencher) {
+    let input = mset_10k_pairs();
+
+    bench.iter(|| {
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+        command_iter.next().unwrap()
+    })
+}
+
+/// Benchmark parsing a SET with a 16MB value. The buffer grows as the value
+/// arrives, so this measures the copies of its doubling, see consume_bytes
+fn parse_set_large_value(bench: &mut Bencher) {
+    const VALUE_SIZE: usize = 16 * 1024 * 1024;
+    let mut input = format!("*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n${VALUE_SIZE}\r\n").into_bytes();
+    input.resize(input.len() + VALUE_SIZE, b'a');
+    input.extend_from_slice(b"\r\n");
+
+    bench.iter(|| {
+        let mut command_iter = CommandIter::new(input.as_slice());
+        command_iter.next().unwrap()
+    })
+}
+
+/// Benchmark parsing a pipeline of 1000 GETs, where the per-command overhead
+/// of the parser dominates rather than the size of the arguments. This measures
+/// time only, the allocations per command are counted in tests/allocation_test.rs
+fn parse_get_pipeline(bench: &mut Bencher) {
+    let input = "*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n".repeat(1000);
+
+    bench.iter(|| {
+        for command in CommandIter::new(input.as_bytes()) {
+            command.unwrap();
+        }
+    })
+}
+
+/// Benchmark the path a command takes in the server: parse, handle by the core
+/// and encode the response. Each iteration runs 100 commands against the same core
+fn request_response(bench: &mut Bencher, command: &[&str]) {
+    let mut request = format!("*{}\r\n", command.len());
+    for argument in command {
+        request += &format!("${}\r\n{}\r\n", argument.len(), argument);
+    }
+    let input = request.repeat(100);
+
+    let mut core = Core::new();
+    bench.iter(|| {
+        for command in CommandIter::new(MockTcpStream::new(input.as_bytes())) {
+            outbound::encode(core.handle_command(command.unwrap()));
+        }
+    })
+}
+
+fn request_response_get(bench: &mut Bencher) {
+    request_response(bench, &["GET", "key"])
+}
+
+fn request_response_set(bench: &mut Bencher) {
+    request_response(bench, &["SET", "key", "value"])
+}
+
+fn request_response_incr(bench: &mut Bencher) {
+    request_response(bench, &["INCR", "counter"])
+}
+
+fn request_response_lpush(bench: &mut Bencher) {
+    request_response(bench, &["LPUSH", "list", "value"])
+}
+
+/// Same as request_response, with a single MSET of 10k pairs, where setting
+/// the pairs costs as much as parsing them
+fn request_response_mset_10k_pairs(bench: &mut Bencher) {
+    let input = mset_10k_pairs();
+
+    let mut core = Core::new();
+    bench.iter(|| {
+        for command in CommandIter::new(MockTcpStream::new(input.as_bytes())) {
+            outbound::encode(core.handle_command(command.unwrap()));
+        }
+    })
+}
+
 benchmark_group!(
     job_queue,
     deque_single_thread_enqueue,
@@ -142,4 +238,47 @@ benchmark_group!(
     disruptor_multi_thread_enqueue,
     disruptor_single_thread_dequeue
 );
-benchmark_main!(job_queue);
+benchmark_group!(
+    parser,
+    parse_mset_10k_pairs,
+    parse_set_large_value,
+    parse_get_pipeline
+);
+/// Benchmark GET on a key with an expiration, with the clock read on every
+/// expiration check or the coarse clock refreshed by the expiration timer
+fn get_with_expiration(bench: &mut Bencher, coarse_clock: bool) {
+    let mut core = Core::new();
+    core.set_coarse_clock(coarse_clock);
+    core.handle_command(Command::Set(Key(b"key".to_vec()), b"value".to_vec()));
+    core.handle_command(Command::Expire(Key(b"key".to_vec()), 3600));
+    let command = Command::Get(Key(b"key".to_vec()));
+
+    bench.iter(|| {
+        for _ in 0..100 {
+            core.handle_command(command.clone());
+        }
+    })
+}
+
+fn get_with_expiration_clock(bench: &mut Bencher) {
+    get_with_expiration(bench, false)
+}
+
+fn get_with_expiration_coarse_clock(bench: &mut Bencher) {
+    get_with_expiration(bench, true)
+}
+
+benchmark_group!(
+    request_response_path,
+    request_response_get,
+    request_response_set,
+    request_response_incr,
+    request_response_lpush,
+    request_response_mset_10k_pairs
+);
+benchmark_group!(
+    expiration_clock,
+    get_with_expiration_clock,
+    get_with_expiration_coarse_clock
+);
+benchmark_main!(job_queue, parser, request_response_path, expiration_clock);
@@ -0,0 +1,294 @@
+/// This module turns redis.conf-style configuration into a ServerBuilder
+/// Each directive is a keyword followed by its arguments, e.g. `port 6379`
+/// The same directives can be passed as command-line flags, e.g. `--port 6379`
+use std::fs;
+
+use crate::server::{ConfigError, JobQueueKind, Server, ServerBuilder};
+
+pub fn read_file(path: &str) -> Result<ServerBuilder, ConfigError> {
+    let content = fs::read_to_string(path).map_err(ConfigError::IoError)?;
+    parse_file(&content)
+}
+
+/// Parse the content of a config file: one directive per line, empty lines
+/// and lines starting with # are skipped
+pub fn parse_file(content: &str) -> Result<ServerBuilder, ConfigError> {
+    let mut builder = Server::builder();
+
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace().map(unquote);
+        let directive = words.next().unwrap().to_lowercase();
+        let arguments = words.collect::<Vec<&str>>();
+        builder = apply_directive(builder, &directive, &arguments)?;
+    }
+
+    Ok(builder)
+}
+
+/// Parse command-line arguments (without the program name), following redis-server
+/// conventions: every `--directive` is followed by its arguments. A config file given
+/// with `--config path` is applied first, then the other flags override it
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<ServerBuilder, ConfigError> {
+    let mut directives: Vec<(String, Vec<String>)> = vec![];
+
+    for arg in args {
+        match arg.strip_prefix("--") {
+            Some(directive) => directives.push((directive.to_lowercase(), vec![])),
+            None => match directives.last_mut() {
+                Some((_, arguments)) => arguments.push(arg),
+                None => return Err(ConfigError::InvalidValue(String::from("argument"), arg)),
+            },
+        }
+    }
+
+    let mut builder = match directives
+        .iter()
+        .find(|(directive, _)| directive == "config")
+    {
+        Some((_, arguments)) => match arguments.first() {
+            Some(path) => read_file(path)?,
+            None => {
+                return Err(ConfigError::InvalidValue(
+                    String::from("config"),
+                    String::new(),
+                ))
+            }
+        },
+        None => Server::builder(),
+    };
+
+    for (directive, arguments) in directives
+        .iter()
+        .filter(|(directive, _)| directive != "config")
+    {
+        let arguments = arguments.iter().map(String::as_str).collect::<Vec<&str>>();
+        builder = apply_directive(builder, directive, &arguments)?;
+    }
+
+    Ok(builder)
+}
+
+fn apply_directive(
+    builder: ServerBuilder,
+    directive: &str,
+    arguments: &[&str],
+) -> Result<ServerBuilder, ConfigError> {
+    let value = match arguments.first() {
+        Some(value) => *value,
+        None => {
+            return Err(ConfigError::InvalidValue(
+                directive.to_owned(),
+                String::new(),
+            ))
+        }
+    };
+
+    let builder = match directive {
+        "bind" => {
+            if arguments.len() > 1 {
+                log::warn!("Only one bind address is supported, listening on {value}");
+            }
+
+            builder.interface(value)
+        }
+        "port" => builder.port(parse_number(directive, value)?),
+        "maxclients" => builder.max_clients(parse_number(directive, value)?),
+        "tcp-nodelay" => builder.tcp_nodelay(parse_yes_no(directive, value)?),
+        "list-max-length" => builder.list_max_length(parse_number(directive, value)?),
+        // Negative values are size limits in bytes, which don't apply as lists aren't packed
+        "list-max-listpack-size" if value.starts_with('-') => {
+            log::warn!("Only positive values are supported for {directive}, ignoring");
+            builder
+        }
+        "list-max-listpack-size" => builder.list_max_listpack_size(parse_number(directive, value)?),
+        "tcp-backlog" => {
+            let tcp_backlog = parse_number(directive, value)?;
+            let tcp_backlog = i32::try_from(tcp_backlog)
+                .map_err(|_| ConfigError::InvalidValue(directive.to_owned(), value.to_owned()))?;
+            builder.tcp_backlog(tcp_backlog)
+        }
+        "job-queue" => builder.job_queue(parse_job_queue(directive, value)?),
+        "daemonize" => builder.daemonize(parse_yes_no(directive, value)?),
+        "pidfile" => builder.pidfile(value),
+        // Running without the password the operator asked for is not an option
+        "requirepass" => return Err(ConfigError::Unsupported(directive.to_owned())),
+        "maxmemory" | "appendonly" => {
+            log::warn!("Config directive {directive} is not supported, ignoring");
+            builder
+        }
+        _ => {
+            log::warn!("Unknown config directive {directive}, ignoring");
+            builder
+        }
+    };
+
+    Ok(builder)
+}
+
+fn parse_number(directive: &str, value: &str) -> Result<usize, ConfigError> {
+    value
+        .parse::<usize>()
+        .map_err(|_| ConfigError::InvalidValue(directive.to_owned(), value.to_owned()))
+}
+
+fn parse_yes_no(directive: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(ConfigError::InvalidValue(
+            directive.to_owned(),
+            value.to_owned(),
+        )),
+    }
+}
+
+fn parse_job_queue(directive: &str, value: &str) -> Result<JobQueueKind, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "deque" => Ok(JobQueueKind::Deque),
+        "channel" => Ok(JobQueueKind::Channel),
+        "disruptor" => Ok(JobQueueKind::Disruptor),
+        _ => Err(ConfigError::InvalidValue(
+            directive.to_owned(),
+            value.to_owned(),
+        )),
+    }
+}
+
+fn unquote(word: &str) -> &str {
+    word.strip_prefix('"')
+        .and_then(|word| word.strip_suffix('"'))
+        .unwrap_or(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, parse_file};
+    use crate::server::{ConfigError, JobQueueKind, Server};
+
+    #[test]
+    fn directives() {
+        let content = "
+            # Comment
+            bind \"10.0.0.1\"
+            PORT 6380
+
+            maxclients 64
+            tcp-keepalive 300
+            tcp-backlog 128
+            tcp-nodelay no
+            list-max-length 1000
+            list-max-listpack-size 64
+            job-queue channel
+        ";
+        let server = parse_file(content).unwrap().build().unwrap();
+
+        assert_eq!(server.interface, "10.0.0.1");
+        assert_eq!(server.port, 6380);
+        assert_eq!(server.max_clients, 64);
+        assert_eq!(server.tcp_backlog, 128);
+        assert!(!server.tcp_nodelay);
+        assert_eq!(server.list_max_length, Some(1000));
+        assert_eq!(server.list_max_listpack_size, 64);
+        assert_eq!(server.job_queue, JobQueueKind::Channel);
+    }
+
+    #[test]
+    fn invalid_values() {
+        let result = parse_file("port abc");
+        assert!(matches!(result, Err(ConfigError::InvalidValue(_, _))));
+
+        let result = parse_file("maxclients");
+        assert!(matches!(result, Err(ConfigError::InvalidValue(_, _))));
+
+        let result = parse_file("requirepass secret");
+        assert!(matches!(result, Err(ConfigError::Unsupported(_))));
+
+        let result = parse_file("daemonize maybe");
+        assert!(matches!(result, Err(ConfigError::InvalidValue(_, _))));
+
+        let result = parse_file("job-queue stack");
+        assert!(matches!(result, Err(ConfigError::InvalidValue(_, _))));
+
+        // The default of redis.conf is ignored rather than rejected
+        let server = parse_file("list-max-listpack-size -2")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(server.list_max_listpack_size, 128);
+    }
+
+    #[test]
+    #[cfg(feature = "daemon")]
+    fn daemon_directives() {
+        let server = parse_file("daemonize yes\npidfile /var/run/tiny_redis.pid")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert!(server.daemonize);
+        assert_eq!(server.pidfile.as_deref(), Some("/var/run/tiny_redis.pid"));
+    }
+
+    #[test]
+    fn flags() {
+        let server = parse_args(args(&[
+            "--port",
+            "6380",
+            "--bind",
+            "0.0.0.0",
+            "--maxclients",
+            "10",
+            "--job-queue",
+            "Disruptor",
+        ]))
+        .unwrap()
+        .build()
+        .unwrap();
+
+        assert_eq!(server.interface, "0.0.0.0");
+        assert_eq!(server.port, 6380);
+        assert_eq!(server.max_clients, 10);
+        assert_eq!(server.job_queue, JobQueueKind::Disruptor);
+    }
+
+    #[test]
+    fn flags_defaults() {
+        let server = parse_args(args(&[])).unwrap().build().unwrap();
+        let default = Server::default();
+
+        assert_eq!(server.interface, default.interface);
+        assert_eq!(server.port, default.port);
+        assert_eq!(server.max_clients, default.max_clients);
+    }
+
+    #[test]
+    fn flags_with_config_file() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/redis.conf");
+        let server = parse_args(args(&["--port", "7000", "--config", path]))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // Flags take precedence over the config file
+        assert_eq!(server.interface, "0.0.0.0");
+        assert_eq!(server.port, 7000);
+        assert_eq!(server.max_clients, 128);
+    }
+
+    #[test]
+    fn invalid_flags() {
+        let result = parse_args(args(&["6380"]));
+        assert!(matches!(result, Err(ConfigError::InvalidValue(_, _))));
+
+        let result = parse_args(args(&["--requirepass", "secret"]));
+        assert!(matches!(result, Err(ConfigError::Unsupported(_))));
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+}
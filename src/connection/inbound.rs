@@ -3,14 +3,38 @@
 /// over commands
 use std::{
     borrow::Borrow,
+    collections::VecDeque,
     io,
     io::{BufRead, BufReader, Read},
-    str,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+    time::Duration,
 };
 
-use crate::core::{Command, Key};
+use crate::core::storage::ListEnd;
+use crate::core::{Command, CommandError, DebugSubcommand, HelpFamily, Key, SetCondition};
 
-pub struct CommandIter<T: Read>(pub TokenIter<T>);
+// Same as the multibulk length limit of Redis
+pub const DEFAULT_MAX_ARGUMENTS: usize = 1024 * 1024;
+
+// Same as the default proto-max-bulk-len of Redis
+const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024;
+
+// What a bulk string reserves before any of its bytes arrive
+const INITIAL_BULK_CAPACITY: usize = 64 * 1024;
+
+pub struct CommandIter<T: Read> {
+    tokens: TokenIter<T>,
+    max_arguments: usize,
+    // Reused from one command to the next, to save an allocation per command
+    arguments: VecDeque<Token>,
+    // Set after a protocol error, nothing more can be read from the stream
+    closed: bool,
+    // The number of connections in MONITOR mode. Commands are only kept as
+    // MONITOR shows them while there is at least one
+    monitors: Option<Arc<AtomicUsize>>,
+    monitor_line: Option<String>,
+}
 
 #[derive(Debug)]
 pub enum Error {
@@ -19,24 +43,121 @@ pub enum Error {
     MissingArguments(usize),
     MissingCrlf,
     NotInteger,
+    SyntaxError,
+    // The command name and the beginning of its arguments
+    UnknownCommand(String, String),
+    // A token starts with a byte which is not a RESP type
+    UnexpectedPrefix(u8),
+    // Negative or too large length of a bulk string or an array
+    InvalidLength,
+    // The command has more arguments than CommandIter allows
+    TooManyArguments,
+    // The stream ends in the middle of a command
+    UnexpectedEof,
     IoError(io::Error),
 }
 
+impl Error {
+    /// The error reply for the client. None when the client is gone anyway
+    pub fn reply(&self) -> Option<String> {
+        let reply = match self {
+            Error::MissingArguments(_) => String::from("ERR wrong number of arguments"),
+            Error::NotInteger => CommandError::NotInteger.to_string(),
+            Error::SyntaxError => CommandError::SyntaxError.to_string(),
+            Error::UnknownCommand(command, arguments) => {
+                format!("ERR unknown command '{command}', with args beginning with: {arguments}")
+            }
+            Error::KeyNotFound | Error::UnexpectedToken { .. } => {
+                String::from("ERR Protocol error: unexpected token")
+            }
+            Error::MissingCrlf => String::from("ERR Protocol error: missing CRLF"),
+            Error::UnexpectedPrefix(prefix) => {
+                format!("ERR Protocol error: unexpected '{}'", *prefix as char)
+            }
+            Error::InvalidLength => String::from("ERR Protocol error: invalid length"),
+            Error::TooManyArguments => String::from("ERR Protocol error: too many arguments"),
+            Error::UnexpectedEof | Error::IoError(_) => return None,
+        };
+
+        Some(reply)
+    }
+}
+
 impl<T: Read> CommandIter<T> {
     pub fn new(stream: T) -> Self {
-        Self(TokenIter(BufReader::new(stream)))
+        Self {
+            tokens: TokenIter {
+                reader: BufReader::new(stream),
+                line: vec![],
+            },
+            max_arguments: DEFAULT_MAX_ARGUMENTS,
+            arguments: VecDeque::new(),
+            closed: false,
+            monitors: None,
+            monitor_line: None,
+        }
     }
 
-    fn parse(&mut self) -> Result<Command, Error> {
-        let mut token_iter = &mut self.0;
-        let command_size = command_size(&mut token_iter)?;
-        let command = command(&mut token_iter)?;
-        let mut arguments = arguments(&mut token_iter, command_size - 1)?;
+    /// Commands with more arguments than this are rejected before their
+    /// arguments are read, so a single command can't monopolize the core
+    pub fn max_arguments(mut self, max_arguments: usize) -> Self {
+        self.max_arguments = max_arguments;
+        self
+    }
+
+    pub fn monitors(mut self, monitors: Arc<AtomicUsize>) -> Self {
+        self.monitors = Some(monitors);
+        self
+    }
+
+    /// The last command read, quoted the way MONITOR shows it, e.g. "GET" "key".
+    /// None when no connection was in MONITOR mode at the time
+    pub fn take_monitor_line(&mut self) -> Option<String> {
+        self.monitor_line.take()
+    }
+
+    /// Ok(None) means the stream ends cleanly, right after the last command.
+    /// Err is a protocol error, after which the stream can't be read any further.
+    /// A command which is read completely but is invalid is Ok(Some(Err(_)))
+    fn next_command(&mut self) -> Result<Option<Result<Command, Error>>, Error> {
+        loop {
+            if self.tokens.is_eof()? {
+                return Ok(None);
+            }
 
+            // Like Redis, an empty command (*0) is skipped without a reply
+            let command_size = command_size(&mut self.tokens)?;
+            if command_size > 0 {
+                return self.parse(command_size).map(Some);
+            }
+        }
+    }
+
+    fn parse(&mut self, command_size: usize) -> Result<Result<Command, Error>, Error> {
+        let token_iter = &mut self.tokens;
+        if command_size - 1 > self.max_arguments {
+            return Err(Error::TooManyArguments);
+        }
+
+        let command = command(token_iter)?;
+        read_arguments(token_iter, command_size - 1, &mut self.arguments)?;
+
+        let monitored = self
+            .monitors
+            .as_ref()
+            .is_some_and(|monitors| monitors.load(Ordering::Acquire) > 0);
+        self.monitor_line = monitored.then(|| monitor_line(&command, &self.arguments));
+
+        Ok(Self::to_command(&command, &mut self.arguments))
+    }
+
+    // The whole command is consumed at this point, an error here leaves
+    // the stream at the start of the next command
+    fn to_command(command: &str, arguments: &mut VecDeque<Token>) -> Result<Command, Error> {
         try {
-            match command.borrow() {
+            match command {
                 command @ ("DEL" | "EXISTS") => {
-                    let keys = expect_keys(&mut arguments)?;
+                    let keys = expect_keys(arguments)?;
 
                     match command {
                         "DEL" => Command::Del(keys),
@@ -45,31 +166,226 @@ impl<T: Read> CommandIter<T> {
                     }
                 }
 
-                "EXPIRE" => {
-                    let key = expect_key(&mut arguments)?;
-                    let ttl = expect_binary(&mut arguments)?;
-                    Command::Expire(key, bytes_to_integer(ttl)? as usize)
+                command @ ("EXPIRE" | "PEXPIRE") => {
+                    let key = expect_key(arguments)?;
+                    let ttl = bytes_to_integer(&expect_binary(arguments)?)?;
+                    match command {
+                        "EXPIRE" => Command::Expire(key, ttl),
+                        "PEXPIRE" => Command::PExpire(key, ttl),
+                        _ => unreachable!(),
+                    }
+                }
+
+                command @ ("EXPIREAT" | "PEXPIREAT") => {
+                    let key = expect_key(arguments)?;
+                    let time = bytes_to_integer(&expect_binary(arguments)?)?;
+                    match command {
+                        "EXPIREAT" => Command::ExpireAt(key, time),
+                        "PEXPIREAT" => Command::PExpireAt(key, time),
+                        _ => unreachable!(),
+                    }
+                }
+
+                command @ ("EXPIRETIME" | "PEXPIRETIME") => {
+                    let key = expect_key(arguments)?;
+                    match command {
+                        "EXPIRETIME" => Command::ExpireTime(key),
+                        "PEXPIRETIME" => Command::PExpireTime(key),
+                        _ => unreachable!(),
+                    }
+                }
+
+                "FLUSHALL" => {
+                    // There is no background reclaim, both modes flush right away
+                    if !arguments.is_empty() {
+                        let mode = expect_binary(arguments)?.to_ascii_uppercase();
+                        if !matches!(mode.as_slice(), b"ASYNC" | b"SYNC") || !arguments.is_empty() {
+                            Err(Error::SyntaxError)?
+                        }
+                    }
+
+                    Command::Flush
+                }
+
+                "QUIT" => Command::Quit,
+
+                "DBSIZE" => Command::DbSize,
+
+                "TIME" => Command::Time,
+
+                "ROLE" => Command::Role,
+
+                "MONITOR" => Command::Monitor,
+
+                // SLAVEOF is the former name of REPLICAOF
+                command @ ("REPLICAOF" | "SLAVEOF" | "FAILOVER") => {
+                    arguments.clear();
+                    match command {
+                        "FAILOVER" => Command::Failover,
+                        _ => Command::ReplicaOf,
+                    }
                 }
 
-                "FLUSHALL" => Command::Flush,
+                "INFO" => {
+                    // Sections are ignored, we always reply with everything we have
+                    arguments.clear();
+                    Command::Info
+                }
+
+                command @ ("COMMAND" | "OBJECT" | "DEBUG" | "MEMORY" | "CONFIG" | "CLIENT") => {
+                    let subcommand = bytes_to_string(expect_binary(arguments)?);
+                    let family = match command {
+                        "COMMAND" => HelpFamily::Command,
+                        "OBJECT" => HelpFamily::Object,
+                        "DEBUG" => HelpFamily::Debug,
+                        "MEMORY" => HelpFamily::Memory,
+                        "CONFIG" => HelpFamily::Config,
+                        "CLIENT" => HelpFamily::Client,
+                        _ => unreachable!(),
+                    };
+
+                    match (family, subcommand.to_uppercase().borrow()) {
+                        (family, "HELP") => Command::Help(family),
+                        (HelpFamily::Command, "COUNT") => Command::CommandCount,
+                        (HelpFamily::Command, "LIST") => Command::CommandList,
+                        (HelpFamily::Command, "INFO") => {
+                            Command::CommandInfo(expect_binaries(arguments)?)
+                        }
+                        (HelpFamily::Command, "DOCS") => {
+                            Command::CommandDocs(expect_binaries(arguments)?)
+                        }
+                        (HelpFamily::Object, "ENCODING") => {
+                            let key = expect_key(arguments)?;
+                            Command::ObjectEncoding(key)
+                        }
+                        (HelpFamily::Memory, "USAGE") => {
+                            let key = expect_key(arguments)?;
+                            // Same default as Redis
+                            let mut samples = 5;
+                            if !arguments.is_empty() {
+                                if !expect_binary(arguments)?.eq_ignore_ascii_case(b"SAMPLES") {
+                                    Err(Error::SyntaxError)?
+                                }
+
+                                let count = bytes_to_integer(&expect_binary(arguments)?)?;
+                                samples = usize::try_from(count).or(Err(Error::SyntaxError))?;
+                            }
+
+                            Command::MemoryUsage(key, samples)
+                        }
+                        (HelpFamily::Memory, "DOCTOR") => Command::MemoryDoctor,
+                        (HelpFamily::Memory, "STATS") => Command::MemoryStats,
+                        (HelpFamily::Config, "GET") => {
+                            let mut patterns = vec![expect_binary(arguments)?];
+                            patterns.extend(expect_binaries(arguments)?);
+                            Command::ConfigGet(patterns)
+                        }
+                        (HelpFamily::Config, "SET") => {
+                            let mut pairs = vec![];
+                            loop {
+                                let name = expect_binary(arguments)?;
+                                let value = expect_binary(arguments)?;
+                                pairs.push((name, value));
+
+                                if arguments.is_empty() {
+                                    break Command::ConfigSet(pairs);
+                                }
+                            }
+                        }
+                        (HelpFamily::Client, mode @ ("NO-EVICT" | "NO-TOUCH")) => {
+                            let enabled =
+                                match expect_binary(arguments)?.to_ascii_uppercase().as_slice() {
+                                    b"ON" => true,
+                                    b"OFF" => false,
+                                    _ => Err(Error::SyntaxError)?,
+                                };
+
+                            match mode {
+                                "NO-EVICT" => Command::ClientNoEvict(enabled),
+                                "NO-TOUCH" => Command::ClientNoTouch(enabled),
+                                _ => unreachable!(),
+                            }
+                        }
+                        (HelpFamily::Debug, "RELOAD") => Command::Debug(DebugSubcommand::Reload),
+                        (HelpFamily::Debug, "OBJECT") => {
+                            let key = expect_key(arguments)?;
+                            Command::Debug(DebugSubcommand::Object(key))
+                        }
+                        (HelpFamily::Debug, "EXPIRE") => {
+                            let key = expect_key(arguments)?;
+                            Command::Debug(DebugSubcommand::Expire(key))
+                        }
+                        (HelpFamily::Debug, "SLEEP") => {
+                            let seconds = bytes_to_string(expect_binary(arguments)?);
+                            match seconds.parse().map(Duration::try_from_secs_f64) {
+                                Ok(Ok(duration)) => {
+                                    Command::Debug(DebugSubcommand::Sleep(duration))
+                                }
+                                _ => Err(Error::SyntaxError)?,
+                            }
+                        }
+                        (HelpFamily::Debug, "KEYVERSION") => {
+                            let key = expect_key(arguments)?;
+                            Command::Debug(DebugSubcommand::KeyVersion(key))
+                        }
+                        (HelpFamily::Debug, "SET-ACTIVE-EXPIRE") => {
+                            let enabled = bytes_to_integer(&expect_binary(arguments)?)?;
+                            Command::Debug(DebugSubcommand::SetActiveExpire(enabled != 0))
+                        }
+                        // Tuning knobs of encodings we don't have, accept and ignore them
+                        // There is no replication ID to change
+                        (HelpFamily::Debug, "CHANGE-REPL-ID") => {
+                            Command::Debug(DebugSubcommand::NoOp)
+                        }
+                        (HelpFamily::Debug, "QUICKLIST-PACKED-THRESHOLD" | "STRINGMATCH-LEN") => {
+                            arguments.clear();
+                            Command::Debug(DebugSubcommand::NoOp)
+                        }
+                        _ => Err(Error::UnknownCommand(
+                            format!("{command} {subcommand}"),
+                            arguments_snippet(arguments),
+                        ))?,
+                    }
+                }
 
-                command @ ("GET" | "GETDEL" | "TTL" | "INCR" | "DECR") => {
-                    let key = expect_key(&mut arguments)?;
+                command @ ("GET" | "GETDEL" | "TTL" | "PTTL" | "INCR" | "DECR") => {
+                    let key = expect_key(arguments)?;
                     match command {
                         "GET" => Command::Get(key),
                         "GETDEL" => Command::GetDel(key),
                         "TTL" => Command::Ttl(key),
+                        "PTTL" => Command::PTtl(key),
                         "INCR" => Command::Incr(key),
                         "DECR" => Command::Decr(key),
                         _ => unreachable!(),
                     }
                 }
 
-                command @ ("SET" | "SETNX" | "GETSET" | "INCRBY" | "DECRBY") => {
-                    let key = expect_key(&mut arguments)?;
-                    let value = expect_binary(&mut arguments)?;
+                "SET" => {
+                    let key = expect_key(arguments)?;
+                    let value = expect_binary(arguments)?;
+
+                    let mut condition = None;
+                    while !arguments.is_empty() {
+                        let option = expect_binary(arguments)?.to_ascii_uppercase();
+                        condition = match (option.as_slice(), condition) {
+                            (b"NX", None | Some(SetCondition::Nx)) => Some(SetCondition::Nx),
+                            (b"XX", None | Some(SetCondition::Xx)) => Some(SetCondition::Xx),
+                            _ => Err(Error::SyntaxError)?,
+                        };
+                    }
+
+                    match condition {
+                        Some(condition) => Command::SetIf(key, value, condition),
+                        None => Command::Set(key, value),
+                    }
+                }
+
+                command @ ("SETNX" | "GETSET" | "INCRBY" | "DECRBY" | "APPEND") => {
+                    let key = expect_key(arguments)?;
+                    let value = expect_binary(arguments)?;
                     match command {
-                        "SET" => Command::Set(key, value),
+                        "APPEND" => Command::Append(key, value),
                         "SETNX" => Command::SetNx(key, value),
                         "GETSET" => Command::GetSet(key, value),
                         "INCRBY" => Command::IncrBy(key, value),
@@ -78,10 +394,25 @@ impl<T: Read> CommandIter<T> {
                     }
                 }
 
+                "STRLEN" => Command::Strlen(expect_key(arguments)?),
+
+                command @ ("GETRANGE" | "SETRANGE") => {
+                    let key = expect_key(arguments)?;
+                    let offset = bytes_to_integer(&expect_binary(arguments)?)?;
+                    match command {
+                        "GETRANGE" => {
+                            let end = bytes_to_integer(&expect_binary(arguments)?)?;
+                            Command::GetRange(key, offset, end)
+                        }
+                        "SETRANGE" => Command::SetRange(key, offset, expect_binary(arguments)?),
+                        _ => unreachable!(),
+                    }
+                }
+
                 "MGET" => {
                     let mut keys = vec![];
                     while !arguments.is_empty() {
-                        let key = expect_key(&mut arguments)?;
+                        let key = expect_key(arguments)?;
                         keys.push(key);
                     }
                     Command::MGet(keys)
@@ -94,10 +425,10 @@ impl<T: Read> CommandIter<T> {
 
                     while !arguments.is_empty() {
                         if index % 2 == 0 {
-                            let key = expect_key(&mut arguments)?;
+                            let key = expect_key(arguments)?;
                             keys.push(key);
                         } else {
-                            let value = expect_binary(&mut arguments)?;
+                            let value = expect_binary(arguments)?;
                             values.push(value);
                         }
 
@@ -111,24 +442,26 @@ impl<T: Read> CommandIter<T> {
                     }
                 }
 
-                command @ ("LPUSH" | "RPUSH") => {
-                    let key = expect_key(&mut arguments)?;
-                    let values = expect_binaries(&mut arguments)?;
+                command @ ("LPUSH" | "RPUSH" | "LPUSHX" | "RPUSHX") => {
+                    let key = expect_key(arguments)?;
+                    let values = expect_binaries(arguments)?;
 
                     match command {
                         "LPUSH" => Command::LPush(key, values),
                         "RPUSH" => Command::RPush(key, values),
+                        "LPUSHX" => Command::LPushX(key, values),
+                        "RPUSHX" => Command::RPushX(key, values),
                         _ => unreachable!(),
                     }
                 }
 
                 command @ ("LPOP" | "RPOP") => {
-                    let key = expect_key(&mut arguments)?;
+                    let key = expect_key(arguments)?;
                     let count = if arguments.is_empty() {
                         1
                     } else {
-                        let value = expect_binary(&mut arguments)?;
-                        bytes_to_integer(value)?
+                        let value = expect_binary(arguments)?;
+                        bytes_to_integer(&value)?
                     };
 
                     match command {
@@ -138,63 +471,138 @@ impl<T: Read> CommandIter<T> {
                     }
                 }
 
-                _ => unimplemented!(),
+                "LMPOP" => {
+                    let numkeys = bytes_to_integer(&expect_binary(arguments)?)?;
+                    if numkeys <= 0 {
+                        Err(Error::SyntaxError)?
+                    }
+
+                    let mut keys = vec![];
+                    for _ in 0..numkeys {
+                        keys.push(expect_key(arguments)?);
+                    }
+
+                    let list_end = match expect_binary(arguments)?.to_ascii_uppercase().as_slice() {
+                        b"LEFT" => ListEnd::Front,
+                        b"RIGHT" => ListEnd::Back,
+                        _ => Err(Error::SyntaxError)?,
+                    };
+
+                    let count = if arguments.is_empty() {
+                        None
+                    } else {
+                        let option = expect_binary(arguments)?.to_ascii_uppercase();
+                        let count = bytes_to_integer(&expect_binary(arguments)?)?;
+                        if option != b"COUNT" || count <= 0 || !arguments.is_empty() {
+                            Err(Error::SyntaxError)?
+                        }
+
+                        Some(count as usize)
+                    };
+
+                    Command::LMPop(keys, list_end, count)
+                }
+
+                command => Err(Error::UnknownCommand(
+                    command.to_owned(),
+                    arguments_snippet(arguments),
+                ))?,
             }
         }
     }
 }
 
+/// Parse all the commands in a buffer, same as CommandIter. Whatever the input
+/// is, this never panics, which makes it a suitable fuzzing entry point
+pub fn parse_commands(buf: &[u8]) -> Vec<Result<Command, Error>> {
+    CommandIter::new(buf).collect()
+}
+
+/// Invalid commands are yielded as errors and the iteration goes on. A protocol
+/// error is yielded last, as the position of the next command is unknown after it
 impl<T: Read> Iterator for CommandIter<T> {
-    type Item = Command;
+    type Item = Result<Command, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.parse().ok()
+        if self.closed {
+            return None;
+        }
+
+        match self.next_command() {
+            Ok(Some(result)) => Some(result),
+            Ok(None) => {
+                log::debug!("Connection closed by client");
+                None
+            }
+            Err(error) => {
+                match error {
+                    Error::UnexpectedEof => {
+                        log::warn!("Connection closed in the middle of a command, dropping it")
+                    }
+                    _ => log::warn!("Protocol error: {error:?}"),
+                }
+
+                self.closed = true;
+                Some(Err(error))
+            }
+        }
     }
 }
 
 fn command_size<T: Read>(token_iter: &mut TokenIter<T>) -> Result<usize, Error> {
-    let token = token_iter.next();
-
-    if let Some(Token::Array(size)) = token {
-        Ok(size)
-    } else {
-        Err(Error::UnexpectedToken {
+    match token_iter.next_token()? {
+        Token::Array(size) => Ok(size),
+        token => Err(Error::UnexpectedToken {
             expect: Token::Array(0),
-            found: None,
-        })
+            found: Some(token),
+        }),
     }
 }
 
 fn command<T: Read>(token_iter: &mut TokenIter<T>) -> Result<String, Error> {
-    let token = token_iter.next();
-    if let Some(Token::String(command)) = token {
-        Ok(bytes_to_string(command))
-    } else {
-        Err(Error::UnexpectedToken {
+    match token_iter.next_token()? {
+        Token::String(command) => Ok(bytes_to_string(command)),
+        token => Err(Error::UnexpectedToken {
             expect: Token::String(vec![]),
-            found: None,
-        })
+            found: Some(token),
+        }),
     }
 }
 
-fn arguments<T: Read>(token_iter: &mut TokenIter<T>, num: usize) -> Result<Vec<Token>, Error> {
-    let tokens = token_iter.take(num).collect::<Vec<Token>>();
-    if tokens.len() == num {
-        Ok(tokens)
-    } else {
-        Err(Error::MissingArguments(num - tokens.len()))
+// Arguments are consumed from the front, hence the VecDeque. Whatever the
+// previous command left in it is cleared first
+// Nested arrays are not allowed, their items would be taken for arguments
+fn read_arguments<T: Read>(
+    token_iter: &mut TokenIter<T>,
+    num: usize,
+    arguments: &mut VecDeque<Token>,
+) -> Result<(), Error> {
+    arguments.clear();
+    for _ in 0..num {
+        match token_iter.next_token()? {
+            token @ Token::Array(_) => {
+                return Err(Error::UnexpectedToken {
+                    expect: Token::String(vec![]),
+                    found: Some(token),
+                })
+            }
+            token => arguments.push_back(token),
+        }
     }
+
+    Ok(())
 }
 
-fn expect_key(arguments: &mut Vec<Token>) -> Result<Key, Error> {
-    if let Token::String(vec) = arguments.remove(0) {
-        return Ok(Key(vec));
-    } else {
-        return Err(Error::KeyNotFound);
+fn expect_key(arguments: &mut VecDeque<Token>) -> Result<Key, Error> {
+    match arguments.pop_front() {
+        Some(Token::String(vec)) => Ok(Key(vec)),
+        Some(Token::Integer(integer)) => Ok(Key(integer.to_string().into_bytes())),
+        Some(_) => Err(Error::KeyNotFound),
+        None => Err(Error::MissingArguments(1)),
     }
 }
 
-fn expect_keys(arguments: &mut Vec<Token>) -> Result<Vec<Key>, Error> {
+fn expect_keys(arguments: &mut VecDeque<Token>) -> Result<Vec<Key>, Error> {
     let mut keys = vec![];
 
     while !arguments.is_empty() {
@@ -207,20 +615,20 @@ fn expect_keys(arguments: &mut Vec<Token>) -> Result<Vec<Key>, Error> {
     Ok(keys)
 }
 
-fn expect_binary(arguments: &mut Vec<Token>) -> Result<Vec<u8>, Error> {
-    let first = arguments.remove(0);
-
-    if let Token::String(vec) = first {
-        return Ok(vec);
-    } else {
-        return Err(Error::UnexpectedToken {
+fn expect_binary(arguments: &mut VecDeque<Token>) -> Result<Vec<u8>, Error> {
+    match arguments.pop_front() {
+        Some(Token::String(vec)) => Ok(vec),
+        // Arguments are binary strings, integers are taken as their decimal representation
+        Some(Token::Integer(integer)) => Ok(integer.to_string().into_bytes()),
+        Some(first) => Err(Error::UnexpectedToken {
             expect: Token::String(vec![]),
             found: Some(first),
-        });
+        }),
+        None => Err(Error::MissingArguments(1)),
     }
 }
 
-fn expect_binaries(arguments: &mut Vec<Token>) -> Result<Vec<Vec<u8>>, Error> {
+fn expect_binaries(arguments: &mut VecDeque<Token>) -> Result<Vec<Vec<u8>>, Error> {
     let mut values = vec![];
 
     while !arguments.is_empty() {
@@ -236,58 +644,106 @@ fn expect_binaries(arguments: &mut Vec<Token>) -> Result<Vec<Vec<u8>>, Error> {
 #[derive(Debug)]
 pub enum Token {
     String(Vec<u8>),
+    Integer(isize),
     Array(usize),
 }
 
-pub struct TokenIter<T: Read>(BufReader<T>);
+pub struct TokenIter<T: Read> {
+    reader: BufReader<T>,
+    // Reused by every line read, lines are only lengths and integers
+    line: Vec<u8>,
+}
 
 impl<T: Read> TokenIter<T> {
+    fn is_eof(&mut self) -> Result<bool, Error> {
+        let buffer = self.reader.fill_buf().map_err(Error::IoError)?;
+        Ok(buffer.is_empty())
+    }
+
+    // Large reads go straight into the buffer without passing through the BufReader,
+    // and the buffer then moves all the way into the storage. The declared length
+    // isn't trusted: the buffer only grows as bytes arrive, at most doubling what's
+    // already read, so a huge length followed by a few bytes doesn't reserve the
+    // whole length upfront. The price is the amortised copies of the doubling, e.g.
+    // about 11 reallocations for a 100MB value, each of which may copy what's read
     fn consume_bytes(&mut self, amount: usize) -> Result<Vec<u8>, Error> {
-        let mut buffer: Vec<u8> = vec![0; amount];
-        self.0
-            .read_exact(&mut buffer)
-            .map_err(|err| -> _ { Error::IoError(err) })?;
+        let mut buffer: Vec<u8> = Vec::with_capacity(amount.min(INITIAL_BULK_CAPACITY));
+        while buffer.len() < amount {
+            let chunk = (amount - buffer.len()).min(buffer.len().max(INITIAL_BULK_CAPACITY));
+            buffer.reserve_exact(chunk);
+
+            let read = (&mut self.reader)
+                .take(chunk as u64)
+                .read_to_end(&mut buffer)
+                .map_err(Error::IoError)?;
+            if read < chunk {
+                return Err(Error::UnexpectedEof);
+            }
+        }
 
         Ok(buffer)
     }
 
+    // Small fixed reads, e.g. a prefix or a CRLF, which don't need a buffer
+    fn consume_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut bytes = [0; N];
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+                _ => Error::IoError(err),
+            })?;
+
+        Ok(bytes)
+    }
+
     // Omits the /r and /n char
-    fn consume_line(&mut self) -> Result<Vec<u8>, Error> {
-        let mut buffer = vec![];
-        self.0
-            .read_until(10, &mut buffer)
+    fn consume_line(&mut self) -> Result<&[u8], Error> {
+        self.line.clear();
+        self.reader
+            .read_until(10, &mut self.line)
             .map_err(|err| -> _ { Error::IoError(err) })?;
 
-        if let &[13, 10] = &buffer[buffer.len() - 2..] {
-            buffer.pop();
-            buffer.pop();
-            Ok(buffer)
-        } else {
-            Err(Error::MissingCrlf)
+        // The stream ends before the line feed
+        if self.line.last() != Some(&10) {
+            return Err(Error::UnexpectedEof);
+        }
+
+        match self.line.strip_suffix(&[13, 10]) {
+            Some(line) => Ok(line),
+            None => Err(Error::MissingCrlf),
         }
     }
 
     fn next_token(&mut self) -> Result<Token, Error> {
-        let prefix = self.consume_bytes(1)?;
+        let [prefix] = self.consume_array()?;
 
-        match prefix[0] {
+        match prefix {
             // $
             36 => {
                 let line = self.consume_line()?;
-                let bulk_string_len = bytes_to_integer(line)?;
+                let bulk_string_len = bytes_to_length(line, MAX_BULK_LENGTH)?;
 
                 // Consume the length of the string plus following /r/n
-                let string = self.consume_bytes(bulk_string_len as usize)?;
-                self.consume_bytes(2)?;
-                return Ok(Token::String(string));
+                let string = self.consume_bytes(bulk_string_len)?;
+                if &self.consume_array()? != b"\r\n" {
+                    return Err(Error::MissingCrlf);
+                }
+
+                Ok(Token::String(string))
+            }
+            // :
+            58 => {
+                let line = self.consume_line()?;
+                Ok(Token::Integer(bytes_to_integer(line)?))
             }
             // *
             42 => {
-                let line = self.consume_line().unwrap();
-                let num_of_items = bytes_to_integer(line)?;
-                return Ok(Token::Array(num_of_items as usize));
+                let line = self.consume_line()?;
+                let num_of_items = bytes_to_length(line, isize::MAX as usize)?;
+                Ok(Token::Array(num_of_items))
             }
-            _ => unreachable!(),
+            prefix => Err(Error::UnexpectedPrefix(prefix)),
         }
     }
 }
@@ -306,22 +762,93 @@ impl<T: Read> Iterator for TokenIter<T> {
     }
 }
 
-fn bytes_to_integer(bytes: Vec<u8>) -> Result<isize, Error> {
-    match String::from_utf8(bytes) {
+fn bytes_to_integer(bytes: &[u8]) -> Result<isize, Error> {
+    match std::str::from_utf8(bytes) {
         Ok(string) => string.parse::<isize>().or(Err(Error::NotInteger)),
         Err(_) => Err(Error::NotInteger),
     }
 }
 
+fn bytes_to_length(bytes: &[u8], max: usize) -> Result<usize, Error> {
+    match bytes_to_integer(bytes).map(usize::try_from) {
+        Ok(Ok(length)) if length <= max => Ok(length),
+        _ => Err(Error::InvalidLength),
+    }
+}
+
+// Valid UTF-8 keeps the allocation of the bytes
 fn bytes_to_string(bytes: Vec<u8>) -> String {
-    String::from(str::from_utf8(&bytes).unwrap())
+    String::from_utf8(bytes)
+        .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned())
+}
+
+// The beginning of the arguments, each one quoted, for the unknown command reply.
+// Same as Redis, arguments are added until the snippet reaches 128 bytes
+fn arguments_snippet(arguments: &VecDeque<Token>) -> String {
+    let mut snippet = String::new();
+    for argument in arguments {
+        if snippet.len() >= 128 {
+            break;
+        }
+
+        let argument = match argument {
+            Token::String(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            Token::Integer(integer) => integer.to_string(),
+            Token::Array(_) => continue,
+        };
+        let argument = argument
+            .chars()
+            .take(128 - snippet.len())
+            .collect::<String>();
+        snippet.push_str(&format!("'{argument}' "));
+    }
+
+    snippet
+}
+
+fn monitor_line(command: &str, arguments: &VecDeque<Token>) -> String {
+    let mut line = quote(command.as_bytes());
+    for argument in arguments {
+        let argument = match argument {
+            Token::String(bytes) => quote(bytes),
+            Token::Integer(integer) => quote(integer.to_string().as_bytes()),
+            Token::Array(_) => continue,
+        };
+        line.push(' ');
+        line.push_str(&argument);
+    }
+
+    line
+}
+
+// Same as Redis, special and non-printable bytes are escaped
+fn quote(bytes: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for byte in bytes {
+        match byte {
+            b'\\' => quoted.push_str("\\\\"),
+            b'"' => quoted.push_str("\\\""),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            byte if byte.is_ascii_graphic() || *byte == b' ' => quoted.push(*byte as char),
+            byte => quoted.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    quoted.push('"');
+
+    quoted
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::connection::inbound::CommandIter;
+    use crate::connection::inbound::{parse_commands, CommandIter, Error};
     use crate::connection::mock_tcp_stream::MockTcpStream;
-    use crate::core::Command;
+    use crate::core::command_table::COMMANDS;
+    use crate::core::storage::ListEnd;
+    use crate::core::{Command, HelpFamily, Key, SetCondition};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn get() {
@@ -330,13 +857,371 @@ mod tests {
         let stream = MockTcpStream::new(input.as_bytes());
 
         let mut command_iter = CommandIter::new(stream);
-        if let Some(Command::Get(key)) = command_iter.next() {
+        if let Some(Ok(Command::Get(key))) = command_iter.next() {
             assert_eq!(key.0, "key".as_bytes());
         } else {
             panic!("Failed to parse command");
         }
     }
 
+    #[test]
+    fn object_help() {
+        let input = "*2\r\n$6\r\nOBJECT\r\n$4\r\nhelp\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::Help(HelpFamily::Object)))
+        ));
+    }
+
+    #[test]
+    fn client_modes() {
+        let input = "*3\r\n$6\r\nCLIENT\r\n$8\r\nNO-TOUCH\r\n$2\r\nON\r\n*3\r\n$6\r\nCLIENT\r\n$8\r\nno-evict\r\n$3\r\noff\r\n*3\r\n$6\r\nCLIENT\r\n$8\r\nNO-TOUCH\r\n$5\r\nmaybe\r\n";
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::ClientNoTouch(true)))
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::ClientNoEvict(false)))
+        ));
+        assert!(matches!(command_iter.next(), Some(Err(Error::SyntaxError))));
+    }
+
+    #[test]
+    fn config() {
+        let input = "*4\r\n$6\r\nCONFIG\r\n$3\r\nGET\r\n$1\r\n*\r\n$4\r\nlist\r\n*5\r\n$6\r\nCONFIG\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n*4\r\n$6\r\nCONFIG\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::ConfigGet(patterns))) if patterns.len() == 2
+        ));
+        // A parameter without a value
+        assert!(matches!(
+            command_iter.next(),
+            Some(Err(Error::MissingArguments(_)))
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::ConfigSet(pairs))) if pairs == vec![(b"a".to_vec(), b"1".to_vec())]
+        ));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let input = "*3\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n$3\r\nkey\r\n*5\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n$3\r\nkey\r\n$7\r\nsamples\r\n$1\r\n0\r\n*5\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n$3\r\nkey\r\n$7\r\nSAMPLES\r\n$2\r\n-1\r\n";
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::MemoryUsage(_, 5)))
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::MemoryUsage(_, 0)))
+        ));
+        assert!(matches!(command_iter.next(), Some(Err(Error::SyntaxError))));
+    }
+
+    #[test]
+    fn missing_arguments() {
+        let input = "*1\r\n$3\r\nGET\r\n*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        assert!(matches!(
+            command_iter.next(),
+            Some(Err(Error::MissingArguments(_)))
+        ));
+        // The invalid command doesn't affect the next one
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Get(_)))));
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let input = "*4\r\n$3\r\nDEL\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n";
+
+        let stream = MockTcpStream::new(input.as_bytes());
+        let mut command_iter = CommandIter::new(stream).max_arguments(2);
+        assert!(matches!(
+            command_iter.next(),
+            Some(Err(Error::TooManyArguments))
+        ));
+        assert!(command_iter.next().is_none());
+
+        let stream = MockTcpStream::new(input.as_bytes());
+        let mut command_iter = CommandIter::new(stream).max_arguments(3);
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Del(keys))) if keys.len() == 3));
+    }
+
+    #[test]
+    fn monitor_line() {
+        let input =
+            "*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$6\r\na\"b\n\x01c\r\n*2\r\n$3\r\nGET\r\n:12\r\n";
+        let monitors = Arc::new(AtomicUsize::new(0));
+        let stream = MockTcpStream::new(input.as_bytes());
+        let mut command_iter = CommandIter::new(stream).monitors(monitors.clone());
+
+        // Nothing is kept without a connection in MONITOR mode
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Set(_, _)))));
+        assert_eq!(command_iter.take_monitor_line(), None);
+
+        monitors.store(1, Ordering::Release);
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Get(_)))));
+        assert_eq!(
+            command_iter.take_monitor_line().as_deref(),
+            Some(r#""GET" "12""#)
+        );
+        assert_eq!(command_iter.take_monitor_line(), None);
+
+        let stream = MockTcpStream::new(input.as_bytes());
+        let mut command_iter = CommandIter::new(stream).monitors(monitors);
+        command_iter.next();
+        assert_eq!(
+            command_iter.take_monitor_line().as_deref(),
+            Some(r#""SET" "key" "a\"b\n\x01c""#)
+        );
+    }
+
+    #[test]
+    fn empty_command() {
+        let input = "*0\r\n*0\r\n*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n*0\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Get(_)))));
+        assert!(command_iter.next().is_none());
+    }
+
+    #[test]
+    fn malformed_inputs_dont_panic() {
+        let valid = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n:10\r\n*2\r\n$4\r\nLPOP\r\n$4\r\nlist\r\n";
+        let mut corpus: Vec<Vec<u8>> = (0..valid.len()).map(|len| valid[..len].to_vec()).collect();
+
+        corpus.extend(
+            [
+                "?\r\n",
+                "*1\r\n?\r\n",
+                "*-1\r\n",
+                "*abc\r\n",
+                "*99999999999999999999\r\n",
+                "*1\r\n$-1\r\n",
+                "*1\r\n$1000000000000\r\nGET\r\n",
+                "*1\r\n$3\r\nGETxx",
+                "*1\r\n:1\r\n",
+                "*1\r\n*1\r\n$3\r\nGET\r\n",
+                "*2\r\n$3\r\nGET\r\n*1\r\n",
+                "*1\r\n$0\r\n\r\n",
+                "*1\r\n$7\r\nBOGUSCM\r\n",
+                "*2\r\n$6\r\nOBJECT\r\n$5\r\nBOGUS\r\n",
+                "*1\r\n$3\r\nGET\n",
+                "*3\r\n$4\r\nMSET\r\n$1\r\na\r\n$1\r\nb\r\n",
+                "*3\r\n$6\r\nEXPIRE\r\n$1\r\na\r\n$3\r\nabc\r\n",
+            ]
+            .iter()
+            .map(|input| input.as_bytes().to_vec()),
+        );
+        // Every byte of the valid input replaced by a byte meaningful to the protocol
+        for position in 0..valid.len() {
+            for byte in b"*$:-0\r\nx" {
+                let mut input = valid.to_vec();
+                input[position] = *byte;
+                corpus.push(input);
+            }
+        }
+        // A command name which is not UTF-8
+        corpus.push(b"*1\r\n$2\r\n\xff\xfe\r\n".to_vec());
+
+        for input in corpus {
+            parse_commands(&input);
+        }
+
+        let commands = parse_commands(valid);
+        assert!(matches!(
+            commands.as_slice(),
+            [Ok(Command::Set(_, _)), Ok(Command::LPop(_, 1))]
+        ));
+
+        let commands = parse_commands(b"*1\r\n$4\r\nBOGUS\r\n");
+        assert!(matches!(commands.as_slice(), [Err(Error::MissingCrlf)]));
+
+        let commands = parse_commands(b"*1\r\n$5\r\nBOGUS\r\n");
+        assert!(
+            matches!(commands.as_slice(), [Err(Error::UnknownCommand(command, _))] if command == "BOGUS")
+        );
+
+        // Long arguments are cut, the snippet stays around 128 bytes
+        let long = "a".repeat(200);
+        let input = format!("*3\r\n$5\r\nBOGUS\r\n$3\r\nfoo\r\n$200\r\n{long}\r\n");
+        let commands = parse_commands(input.as_bytes());
+        let reply = commands[0].as_ref().unwrap_err().reply().unwrap();
+        let expected = format!(
+            "ERR unknown command 'BOGUS', with args beginning with: 'foo' '{}' ",
+            &long[..122]
+        );
+        assert_eq!(reply, expected);
+    }
+
+    #[test]
+    fn clean_eof() {
+        let input = "*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        assert!(matches!(
+            command_iter.next_command(),
+            Ok(Some(Ok(Command::Get(_))))
+        ));
+        assert!(matches!(command_iter.next_command(), Ok(None)));
+    }
+
+    #[test]
+    fn eof_mid_command() {
+        let truncated_inputs = [
+            // In the middle of a bulk string
+            "*2\r\n$3\r\nGET\r\n$3\r\nke",
+            // Before the last argument
+            "*2\r\n$3\r\nGET\r\n",
+            // In the middle of a line
+            "*2\r\n$3\r\nGET\r\n$3",
+            // The largest length allowed, with a few of its bytes
+            "*2\r\n$3\r\nGET\r\n$536870912\r\nkey",
+            // Across several reservations of the buffer
+            &format!("*2\r\n$3\r\nGET\r\n$1000000\r\n{}", "a".repeat(500_000)),
+        ];
+
+        for truncated in &truncated_inputs {
+            let input = format!("*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n{truncated}");
+            let stream = MockTcpStream::new(input.as_bytes());
+
+            let mut command_iter = CommandIter::new(stream);
+            assert!(matches!(
+                command_iter.next_command(),
+                Ok(Some(Ok(Command::Get(_))))
+            ));
+            assert!(matches!(
+                command_iter.next_command(),
+                Err(Error::UnexpectedEof)
+            ));
+        }
+    }
+
+    #[test]
+    fn integer_arguments() {
+        let input = "*3\r\n$6\r\nEXPIRE\r\n:100\r\n:10\r\n*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n:42\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        if let Some(Ok(Command::Expire(key, _))) = command_iter.next() {
+            assert_eq!(key.0, b"100");
+        } else {
+            panic!("Failed to parse command");
+        }
+
+        if let Some(Ok(Command::Set(key, value))) = command_iter.next() {
+            assert_eq!(key.0, b"key");
+            assert_eq!(value, b"42");
+        } else {
+            panic!("Failed to parse command");
+        }
+    }
+
+    #[test]
+    fn set_options() {
+        let input = "*4\r\n$3\r\nSET\r\n$3\r\nkey\r\n$1\r\n1\r\n$2\r\nnx\r\n*4\r\n$3\r\nSET\r\n$3\r\nkey\r\n$1\r\n1\r\n$2\r\nXX\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::SetIf(_, _, SetCondition::Nx)))
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::SetIf(_, _, SetCondition::Xx)))
+        ));
+
+        let input = "*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$1\r\n1\r\n$2\r\nNX\r\n$2\r\nXX\r\n";
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        assert!(matches!(command_iter.next(), Some(Err(Error::SyntaxError))));
+    }
+
+    #[test]
+    fn lmpop() {
+        let input = "*5\r\n$5\r\nLMPOP\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$4\r\nleft\r\n*6\r\n$5\r\nLMPOP\r\n$1\r\n1\r\n$1\r\na\r\n$5\r\nRIGHT\r\n$5\r\nCOUNT\r\n$1\r\n3\r\n";
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::LMPop(keys, ListEnd::Front, None))) if keys.len() == 2
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::LMPop(keys, ListEnd::Back, Some(3)))) if keys.len() == 1
+        ));
+
+        let invalid_inputs = [
+            // No key
+            "*4\r\n$5\r\nLMPOP\r\n$1\r\n0\r\n$1\r\na\r\n$4\r\nLEFT\r\n",
+            // Unknown direction
+            "*4\r\n$5\r\nLMPOP\r\n$1\r\n1\r\n$1\r\na\r\n$2\r\nUP\r\n",
+            // Zero count
+            "*6\r\n$5\r\nLMPOP\r\n$1\r\n1\r\n$1\r\na\r\n$4\r\nLEFT\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n",
+        ];
+
+        for input in invalid_inputs {
+            let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+            assert!(matches!(command_iter.next(), Some(Err(Error::SyntaxError))));
+        }
+    }
+
+    #[test]
+    fn flushall() {
+        let input = "*2\r\n$8\r\nFLUSHALL\r\n$5\r\nASYNC\r\n*2\r\n$8\r\nFLUSHALL\r\n$5\r\nBOGUS\r\n*1\r\n$8\r\nFLUSHALL\r\n";
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Flush))));
+        assert!(matches!(command_iter.next(), Some(Err(Error::SyntaxError))));
+        // The invalid command does not affect the ones following it
+        assert!(matches!(command_iter.next(), Some(Ok(Command::Flush))));
+        assert!(command_iter.next().is_none());
+    }
+
+    #[test]
+    fn mset_many_pairs() {
+        let mut input = String::from("*20001\r\n$4\r\nMSET\r\n");
+        for i in 0..10_000 {
+            let key = format!("key{i}");
+            let value = format!("{i}");
+            input += &format!(
+                "${}\r\n{}\r\n${}\r\n{}\r\n",
+                key.len(),
+                key,
+                value.len(),
+                value
+            );
+        }
+        let stream = MockTcpStream::new(input.as_bytes());
+
+        let mut command_iter = CommandIter::new(stream);
+        if let Some(Ok(Command::MSet(keys, values))) = command_iter.next() {
+            assert_eq!(keys.len(), 10_000);
+            assert_eq!(keys[9_999].0, b"key9999");
+            assert_eq!(values[9_999], b"9999");
+        } else {
+            panic!("Failed to parse command");
+        }
+
+        assert!(command_iter.next().is_none());
+    }
+
     #[test]
     fn set() {
         let key = "key";
@@ -345,11 +1230,69 @@ mod tests {
         let stream = MockTcpStream::new(input.as_bytes());
 
         let mut command_iter = CommandIter::new(stream);
-        if let Some(Command::Set(key, value)) = command_iter.next() {
+        if let Some(Ok(Command::Set(key, value))) = command_iter.next() {
             assert_eq!(key.0, "key".as_bytes());
             assert_eq!(value, "123".as_bytes());
         } else {
             panic!("Failed to parse command");
         }
     }
+
+    #[test]
+    fn empty_strings() {
+        let input = "*3\r\n$3\r\nSET\r\n$0\r\n\r\n$5\r\nvalue\r\n*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$0\r\n\r\n*2\r\n$3\r\nGET\r\n$0\r\n\r\n";
+        let mut command_iter = CommandIter::new(MockTcpStream::new(input.as_bytes()));
+
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::Set(Key(key), value))) if key.is_empty() && value == b"value"
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::Set(Key(key), value))) if key == b"key" && value.is_empty()
+        ));
+        assert!(matches!(
+            command_iter.next(),
+            Some(Ok(Command::Get(Key(key)))) if key.is_empty()
+        ));
+        assert!(command_iter.next().is_none());
+    }
+
+    #[test]
+    fn advertised_commands_parse() {
+        // Every command in the table is sent with the fewest arguments it takes.
+        // Containers get HELP, the one subcommand all of them know
+        for spec in COMMANDS {
+            let mut arguments = vec![spec.name.to_uppercase()];
+            if spec.arguments.first().map(|argument| argument.name) == Some("subcommand") {
+                arguments.push(String::from("HELP"));
+            }
+            while arguments.len() < spec.arity.unsigned_abs() {
+                arguments.push(String::from("1"));
+            }
+
+            let mut input = format!("*{}\r\n", arguments.len());
+            for argument in &arguments {
+                input += &format!("${}\r\n{argument}\r\n", argument.len());
+            }
+
+            match parse_commands(input.as_bytes()).as_slice() {
+                [Ok(command)] => {
+                    // SLAVEOF is an alias of REPLICAOF
+                    let expected = if spec.name == "slaveof" {
+                        "replicaof"
+                    } else {
+                        spec.name
+                    };
+                    assert_eq!(command.name(), Some(expected), "{}", spec.name);
+                }
+                [Err(Error::UnknownCommand(..))] => {
+                    panic!("{} is advertised but not parsed", spec.name)
+                }
+                // Placeholder arguments may not be valid, the command is still known
+                [Err(_)] => {}
+                commands => panic!("{}: {commands:?}", spec.name),
+            }
+        }
+    }
 }
@@ -3,20 +3,25 @@ use std::io::{Error, Read};
 // This module facilitates testing
 pub struct MockTcpStream {
     inner_buffer: Vec<u8>,
+    position: usize,
 }
 
 impl MockTcpStream {
     pub fn new(bytes: &[u8]) -> Self {
         MockTcpStream {
             inner_buffer: bytes.to_vec(),
+            position: 0,
         }
     }
 }
 
+// Behaves like a stream which the peer closes after sending all the bytes
 impl Read for MockTcpStream {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        let len = self.inner_buffer.len();
-        buf[..len].clone_from_slice(&self.inner_buffer);
+        let remaining = &self.inner_buffer[self.position..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].clone_from_slice(&remaining[..len]);
+        self.position += len;
         Ok(len)
     }
 }
@@ -40,4 +45,18 @@ mod tests {
         let output = str::from_utf8(&buffer).unwrap();
         assert_eq!(output, "*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n");
     }
+
+    #[test]
+    fn partial_reads() {
+        let mut stream = MockTcpStream::new(b"hello world");
+
+        let mut buffer = [0; 6];
+        assert_eq!(stream.read(&mut buffer).unwrap(), 6);
+        assert_eq!(&buffer, b"hello ");
+        assert_eq!(stream.read(&mut buffer).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"world");
+
+        // End of stream
+        assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+    }
 }
@@ -1,54 +1,64 @@
 /// into binary as per RESP protocol (https://redis.io/docs/reference/protocol-spec/)
 /// This module provides interface to encode command response
+use std::io::{self, Write};
+
 use crate::core::CommandResponse;
 
 pub fn encode(response: CommandResponse) -> Vec<u8> {
     let mut vec = vec![];
+    encode_into(response, &mut vec).expect("Writing into a Vec never fails");
+    vec
+}
+
+/// Write the encoded response straight into writer. Unlike building it piece
+/// by piece, payloads are copied once, into the writer
+pub fn encode_into<W: Write>(response: CommandResponse, writer: &mut W) -> io::Result<()> {
     match response {
         // +OK\r\n
         CommandResponse::SimpleString(bytes) => {
-            vec = [b"+", bytes, b"\r\n"].concat();
+            writer.write_all(b"+")?;
+            writer.write_all(bytes)?;
+            writer.write_all(b"\r\n")
         }
         // $4\r\nBULK\r\n
-        CommandResponse::BulkString(bytes) => {
-            let content = format!("${}\r\n", bytes.len());
-            vec = [content.as_bytes(), bytes.as_slice(), b"\r\n"].concat();
-        }
+        CommandResponse::BulkString(bytes) => encode_bulk_string(&bytes, writer),
+        CommandResponse::BorrowedBulkString(bytes) => encode_bulk_string(bytes, writer),
         // :1000\r\n
-        CommandResponse::Integer(integer) => {
-            vec = [b":", integer.to_string().as_bytes(), b"\r\n"].concat();
-        }
+        CommandResponse::Integer(integer) => write!(writer, ":{integer}\r\n"),
         // -ERROR\r\n
         CommandResponse::Error(string) => {
-            vec = [b"-", string.as_bytes(), b"\r\n"].concat();
+            writer.write_all(b"-")?;
+            writer.write_all(string.as_bytes())?;
+            writer.write_all(b"\r\n")
         }
         // $-1\r\n
-        CommandResponse::Null => {
-            vec.extend_from_slice(b"$-1\r\n");
-        }
+        CommandResponse::Null => writer.write_all(b"$-1\r\n"),
         // "*2\r\n$5\r\nHello\r\n$5\r\nWorld\r\n"
         CommandResponse::Array(items) => {
-            let content = format!("*{}\r\n", items.len());
-            vec.extend_from_slice(content.as_bytes());
+            write!(writer, "*{}\r\n", items.len())?;
 
             for item in items {
-                let mut encoded = encode(item);
-                vec.append(&mut encoded);
+                encode_into(item, writer)?;
             }
+
+            Ok(())
         }
         // *-1\r\n
-        CommandResponse::NullArray => {
-            vec.extend_from_slice(b"*-1\r\n");
-        }
+        CommandResponse::NullArray => writer.write_all(b"*-1\r\n"),
     }
+}
 
-    vec
+fn encode_bulk_string<W: Write>(bytes: &[u8], writer: &mut W) -> io::Result<()> {
+    write!(writer, "${}\r\n", bytes.len())?;
+    writer.write_all(bytes)?;
+    writer.write_all(b"\r\n")
 }
 
 #[cfg(test)]
 mod tests {
-    use super::encode;
+    use super::{encode, encode_into};
     use crate::core::CommandResponse;
+    use std::io::{BufWriter, Write};
 
     #[test]
     fn simple_string() {
@@ -60,6 +70,12 @@ mod tests {
     fn bulk_string() {
         let response = CommandResponse::BulkString(b"Hello World".to_vec());
         assert_eq!(encode(response), b"$11\r\nHello World\r\n");
+
+        let response = CommandResponse::BorrowedBulkString(b"Hello World");
+        assert_eq!(encode(response), b"$11\r\nHello World\r\n");
+
+        let response = CommandResponse::BorrowedBulkString(b"");
+        assert_eq!(encode(response), b"$0\r\n\r\n");
     }
 
     #[test]
@@ -101,4 +117,22 @@ mod tests {
         let response = CommandResponse::NullArray;
         assert_eq!(encode(response), b"*-1\r\n");
     }
+
+    #[test]
+    fn encode_into_writer() {
+        let response = CommandResponse::Array(vec![
+            CommandResponse::SimpleString(b"OK"),
+            CommandResponse::BulkString(b"Hello World".to_vec()),
+            CommandResponse::Array(vec![CommandResponse::Integer(-1), CommandResponse::Null]),
+            CommandResponse::Error(String::from("Goodbye World")),
+            CommandResponse::NullArray,
+        ]);
+        let expected =
+            b"*5\r\n+OK\r\n$11\r\nHello World\r\n*2\r\n:-1\r\n$-1\r\n-Goodbye World\r\n*-1\r\n";
+
+        let mut writer = BufWriter::new(vec![]);
+        encode_into(response, &mut writer).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.into_inner().unwrap(), expected);
+    }
 }
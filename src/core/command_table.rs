@@ -0,0 +1,419 @@
+/// Describes a command we support: its arity, flags and where its keys are,
+/// plus the documentation COMMAND DOCS replies with
+pub struct CommandSpec {
+    pub name: &'static str,
+    // Positive means exactly this many arguments, negative means at least
+    // that many. The command name counts as an argument
+    pub arity: isize,
+    pub flags: &'static [&'static str],
+    pub first_key: isize,
+    pub last_key: isize,
+    pub step: isize,
+    pub summary: &'static str,
+    pub arguments: &'static [Argument],
+}
+
+/// An argument of a command, as described by COMMAND DOCS
+pub struct Argument {
+    pub name: &'static str,
+    pub kind: ArgumentKind,
+    // The keyword which comes before the argument, e.g. COUNT
+    pub token: Option<&'static str>,
+    pub optional: bool,
+    // The argument can be repeated
+    pub multiple: bool,
+}
+
+pub enum ArgumentKind {
+    Key,
+    String,
+    Integer,
+    // A keyword alone, e.g. NX
+    PureToken,
+    // Exactly one of the arguments
+    OneOf(&'static [Argument]),
+    // All the arguments, in order
+    Block(&'static [Argument]),
+}
+
+impl ArgumentKind {
+    /// The name COMMAND DOCS uses for the kind
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArgumentKind::Key => "key",
+            ArgumentKind::String => "string",
+            ArgumentKind::Integer => "integer",
+            ArgumentKind::PureToken => "pure-token",
+            ArgumentKind::OneOf(_) => "oneof",
+            ArgumentKind::Block(_) => "block",
+        }
+    }
+}
+
+const fn spec(
+    name: &'static str,
+    arity: isize,
+    flags: &'static [&'static str],
+    (first_key, last_key, step): (isize, isize, isize),
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        first_key,
+        last_key,
+        step,
+        summary: "",
+        arguments: &[],
+    }
+}
+
+impl CommandSpec {
+    const fn docs(self, summary: &'static str, arguments: &'static [Argument]) -> Self {
+        CommandSpec {
+            summary,
+            arguments,
+            ..self
+        }
+    }
+}
+
+const fn argument(name: &'static str, kind: ArgumentKind) -> Argument {
+    Argument {
+        name,
+        kind,
+        token: None,
+        optional: false,
+        multiple: false,
+    }
+}
+
+const fn key(name: &'static str) -> Argument {
+    argument(name, ArgumentKind::Key)
+}
+
+const fn string(name: &'static str) -> Argument {
+    argument(name, ArgumentKind::String)
+}
+
+const fn integer(name: &'static str) -> Argument {
+    argument(name, ArgumentKind::Integer)
+}
+
+const fn token(name: &'static str, token: &'static str) -> Argument {
+    argument(name, ArgumentKind::PureToken).with_token(token)
+}
+
+impl Argument {
+    const fn with_token(self, token: &'static str) -> Self {
+        Argument {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    const fn optional(self) -> Self {
+        Argument {
+            optional: true,
+            ..self
+        }
+    }
+
+    const fn multiple(self) -> Self {
+        Argument {
+            multiple: true,
+            ..self
+        }
+    }
+}
+
+// Arguments of commands which group their functionalities under subcommands
+const SUBCOMMAND: &[Argument] = &[string("subcommand"), string("arg").optional().multiple()];
+
+const REPLICAOF: &[Argument] = &[argument(
+    "args",
+    ArgumentKind::OneOf(&[
+        argument(
+            "host-port",
+            ArgumentKind::Block(&[string("host"), integer("port")]),
+        ),
+        argument(
+            "no-one",
+            ArgumentKind::Block(&[token("no", "NO"), token("one", "ONE")]),
+        ),
+    ]),
+)];
+
+// Position of the keys: (first, last, step)
+const NO_KEY: (isize, isize, isize) = (0, 0, 0);
+const ONE_KEY: (isize, isize, isize) = (1, 1, 1);
+const ALL_KEYS: (isize, isize, isize) = (1, -1, 1);
+
+/// COMMAND COUNT, COMMAND LIST, COMMAND INFO and COMMAND DOCS are all answered
+/// from this table, so they always agree with each other
+pub const COMMANDS: &[CommandSpec] = &[
+    // Generic commands
+    spec("del", -2, &["write"], ALL_KEYS)
+        .docs("Deletes one or more keys.", &[key("key").multiple()]),
+    spec("exists", -2, &["readonly", "fast"], ALL_KEYS).docs(
+        "Determines whether one or more keys exist.",
+        &[key("key").multiple()],
+    ),
+    spec("expire", -3, &["write", "fast"], ONE_KEY).docs(
+        "Sets the expiration time of a key in seconds.",
+        &[key("key"), integer("seconds")],
+    ),
+    spec("pexpire", -3, &["write", "fast"], ONE_KEY).docs(
+        "Sets the expiration time of a key in milliseconds.",
+        &[key("key"), integer("milliseconds")],
+    ),
+    spec("expireat", -3, &["write", "fast"], ONE_KEY).docs(
+        "Sets the expiration time of a key to a Unix timestamp.",
+        &[key("key"), integer("unix-time-seconds")],
+    ),
+    spec("pexpireat", -3, &["write", "fast"], ONE_KEY).docs(
+        "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+        &[key("key"), integer("unix-time-milliseconds")],
+    ),
+    spec("expiretime", 2, &["readonly", "fast"], ONE_KEY).docs(
+        "Returns the expiration time of a key as a Unix timestamp.",
+        &[key("key")],
+    ),
+    spec("pexpiretime", 2, &["readonly", "fast"], ONE_KEY).docs(
+        "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+        &[key("key")],
+    ),
+    spec("ttl", 2, &["readonly", "fast"], ONE_KEY).docs(
+        "Returns the expiration time in seconds of a key.",
+        &[key("key")],
+    ),
+    spec("pttl", 2, &["readonly", "fast"], ONE_KEY).docs(
+        "Returns the expiration time in milliseconds of a key.",
+        &[key("key")],
+    ),
+    spec("flushall", -1, &["write"], NO_KEY).docs(
+        "Removes all keys.",
+        &[argument(
+            "flush-type",
+            ArgumentKind::OneOf(&[token("async", "ASYNC"), token("sync", "SYNC")]),
+        )
+        .optional()],
+    ),
+    // String commands
+    spec("get", 2, &["readonly", "fast"], ONE_KEY)
+        .docs("Returns the string value of a key.", &[key("key")]),
+    spec("set", -3, &["write", "denyoom"], ONE_KEY).docs(
+        "Sets the string value of a key. The key is created if it doesn't exist.",
+        &[
+            key("key"),
+            string("value"),
+            argument(
+                "condition",
+                ArgumentKind::OneOf(&[token("nx", "NX"), token("xx", "XX")]),
+            )
+            .optional(),
+        ],
+    ),
+    spec("setnx", 3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Sets the string value of a key only when the key doesn't exist.",
+        &[key("key"), string("value")],
+    ),
+    spec("getset", 3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Returns the previous string value of a key after setting it to a new value.",
+        &[key("key"), string("value")],
+    ),
+    spec("getdel", 2, &["write", "fast"], ONE_KEY).docs(
+        "Returns the string value of a key after deleting the key.",
+        &[key("key")],
+    ),
+    spec("mget", -2, &["readonly", "fast"], ALL_KEYS).docs(
+        "Atomically returns the string values of one or more keys.",
+        &[key("key").multiple()],
+    ),
+    spec("mset", -3, &["write", "denyoom"], (1, -1, 2)).docs(
+        "Atomically creates or modifies the string values of one or more keys.",
+        &[argument("data", ArgumentKind::Block(&[key("key"), string("value")])).multiple()],
+    ),
+    spec("incr", 2, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        &[key("key")],
+    ),
+    spec("decr", 2, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        &[key("key")],
+    ),
+    spec("incrby", 3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        &[key("key"), integer("increment")],
+    ),
+    spec("decrby", 3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        &[key("key"), integer("decrement")],
+    ),
+    spec("append", 3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        &[key("key"), string("value")],
+    ),
+    spec("strlen", 2, &["readonly", "fast"], ONE_KEY).docs(
+        "Returns the length of a string value.",
+        &[key("key")],
+    ),
+    spec("getrange", 4, &["readonly"], ONE_KEY).docs(
+        "Returns a substring of the string stored at a key.",
+        &[key("key"), integer("start"), integer("end")],
+    ),
+    spec("setrange", 4, &["write", "denyoom"], ONE_KEY).docs(
+        "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+        &[key("key"), integer("offset"), string("value")],
+    ),
+    // List commands
+    spec("lpush", -3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+        &[key("key"), string("element").multiple()],
+    ),
+    spec("rpush", -3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+        &[key("key"), string("element").multiple()],
+    ),
+    spec("lpushx", -3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Prepends one or more elements to a list only when the list exists.",
+        &[key("key"), string("element").multiple()],
+    ),
+    spec("rpushx", -3, &["write", "denyoom", "fast"], ONE_KEY).docs(
+        "Appends one or more elements to a list only when the list exists.",
+        &[key("key"), string("element").multiple()],
+    ),
+    spec("lmpop", -4, &["write", "movablekeys"], NO_KEY).docs(
+        "Returns multiple elements from a list after removing them. Deletes the list if the last element was popped.",
+        &[
+            integer("numkeys"),
+            key("key").multiple(),
+            argument(
+                "where",
+                ArgumentKind::OneOf(&[token("left", "LEFT"), token("right", "RIGHT")]),
+            ),
+            integer("count").with_token("COUNT").optional(),
+        ],
+    ),
+    spec("lpop", -2, &["write", "fast"], ONE_KEY).docs(
+        "Returns the first elements in a list after removing them.",
+        &[key("key"), integer("count").optional()],
+    ),
+    spec("rpop", -2, &["write", "fast"], ONE_KEY).docs(
+        "Returns the last elements in a list after removing them.",
+        &[key("key"), integer("count").optional()],
+    ),
+    // Connection commands
+    spec("quit", -1, &["noscript", "loading", "stale", "fast", "allow_busy"], NO_KEY)
+        .docs("Closes the connection.", &[]),
+    // Server commands
+    spec("time", 1, &["loading", "stale", "fast"], NO_KEY).docs("Returns the server time.", &[]),
+    spec("role", 1, &["noscript", "loading", "stale", "fast"], NO_KEY)
+        .docs("Returns the replication role.", &[]),
+    spec("replicaof", 3, &["admin", "noscript", "stale"], NO_KEY).docs(
+        "Configures a server as replica of another, or promotes it to a master.",
+        REPLICAOF,
+    ),
+    spec("slaveof", 3, &["admin", "noscript", "stale"], NO_KEY).docs(
+        "Sets a Redis server as a replica of another, or promotes it to being a master.",
+        REPLICAOF,
+    ),
+    spec("failover", -1, &["admin", "noscript", "stale"], NO_KEY).docs(
+        "Starts a coordinated failover from a server to one of its replicas.",
+        &[
+            argument(
+                "target",
+                ArgumentKind::Block(&[
+                    string("host"),
+                    integer("port"),
+                    token("force", "FORCE").optional(),
+                ]),
+            )
+            .with_token("TO")
+            .optional(),
+            token("abort", "ABORT").optional(),
+            integer("milliseconds").with_token("TIMEOUT").optional(),
+        ],
+    ),
+    spec("monitor", 1, &["admin", "noscript", "loading", "stale"], NO_KEY).docs(
+        "Listens for all requests received by the server in real-time.",
+        &[],
+    ),
+    spec("dbsize", 1, &["readonly", "fast"], NO_KEY)
+        .docs("Returns the number of keys in the database.", &[]),
+    spec("info", -1, &["loading", "stale"], NO_KEY).docs(
+        "Returns information and statistics about the server.",
+        &[string("section").optional().multiple()],
+    ),
+    spec("command", -1, &["loading", "stale"], NO_KEY)
+        .docs("A container for command introspection commands.", SUBCOMMAND),
+    spec("object", -2, &["readonly"], NO_KEY)
+        .docs("A container for object introspection commands.", SUBCOMMAND),
+    spec(
+        "debug",
+        -2,
+        &["admin", "noscript", "loading", "stale"],
+        NO_KEY,
+    )
+    .docs("A container for debugging commands.", SUBCOMMAND),
+    spec("memory", -2, &["readonly"], NO_KEY)
+        .docs("A container for memory diagnostics commands.", SUBCOMMAND),
+    spec(
+        "config",
+        -2,
+        &["admin", "noscript", "loading", "stale"],
+        NO_KEY,
+    )
+    .docs("A container for server configuration commands.", SUBCOMMAND),
+    spec("client", -2, &["slow"], NO_KEY)
+        .docs("A container for client connection commands.", SUBCOMMAND),
+];
+
+/// Command names are case insensitive
+pub fn find(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, Argument, ArgumentKind, COMMANDS};
+    use std::collections::HashSet;
+
+    #[test]
+    fn names_are_unique_and_lowercase() {
+        let names = COMMANDS
+            .iter()
+            .map(|spec| spec.name)
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), COMMANDS.len());
+        assert!(names.iter().all(|name| name.to_lowercase() == *name));
+    }
+
+    #[test]
+    fn every_command_is_documented() {
+        for spec in COMMANDS {
+            assert!(!spec.summary.is_empty(), "{} has no summary", spec.name);
+
+            // The position of movable keys depends on the other arguments
+            let has_key_position = spec.first_key > 0 || spec.flags.contains(&"movablekeys");
+            assert_eq!(has_key(spec.arguments), has_key_position, "{}", spec.name);
+        }
+    }
+
+    fn has_key(arguments: &[Argument]) -> bool {
+        arguments.iter().any(|argument| match argument.kind {
+            ArgumentKind::Key => true,
+            ArgumentKind::OneOf(arguments) | ArgumentKind::Block(arguments) => has_key(arguments),
+            _ => false,
+        })
+    }
+
+    #[test]
+    fn find_is_case_insensitive() {
+        assert_eq!(find(b"GET").unwrap().arity, 2);
+        assert_eq!(find(b"mset").unwrap().step, 2);
+        assert!(find(b"unknown").is_none());
+    }
+}
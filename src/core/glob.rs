@@ -0,0 +1,223 @@
+//! Glob-style pattern matching, same as stringmatchlen of Redis
+//! Every command taking a pattern, e.g. CONFIG GET, goes through glob_match
+
+// Patterns nested deeper than this never match, so a pattern full of * can't
+// exhaust the stack. Same limit as Redis
+const MAX_NESTING: usize = 1000;
+
+/// Supported are *, ?, [abc], [a-z], [^abc] and \ to escape any of them.
+/// Same as Redis, * doesn't match an empty target
+pub(crate) fn glob_match(pattern: &[u8], target: &[u8]) -> bool {
+    let mut skip_longer_matches = false;
+    match_from(pattern, target, &mut skip_longer_matches, 0)
+}
+
+fn match_from(
+    mut pattern: &[u8],
+    mut target: &[u8],
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    while let (Some(&byte), Some(&other)) = (pattern.first(), target.first()) {
+        match byte {
+            b'*' => {
+                // Consecutive stars match the same as a single one
+                while pattern.get(1) == Some(&b'*') {
+                    pattern = &pattern[1..];
+                }
+
+                if pattern.len() == 1 {
+                    return true;
+                }
+
+                while !target.is_empty() {
+                    if match_from(&pattern[1..], target, skip_longer_matches, nesting + 1) {
+                        return true;
+                    }
+
+                    // The rest of the pattern needs more of the target than is left,
+                    // starting further only leaves less of it
+                    if *skip_longer_matches {
+                        return false;
+                    }
+
+                    target = &target[1..];
+                }
+
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => pattern = &pattern[1..],
+            b'[' => {
+                let (matched, rest) = match_class(&pattern[1..], other);
+                if !matched {
+                    return false;
+                }
+
+                pattern = rest;
+            }
+            // A trailing \ is taken literally
+            b'\\' if pattern.len() >= 2 => {
+                if pattern[1] != other {
+                    return false;
+                }
+
+                pattern = &pattern[2..];
+            }
+            _ => {
+                if byte != other {
+                    return false;
+                }
+
+                pattern = &pattern[1..];
+            }
+        }
+
+        target = &target[1..];
+        if target.is_empty() {
+            while pattern.first() == Some(&b'*') {
+                pattern = &pattern[1..];
+            }
+        }
+    }
+
+    pattern.is_empty() && target.is_empty()
+}
+
+// The class starts right after the [. Returns whether byte is in it, and the
+// pattern after its ]. A class without ] runs to the end of the pattern
+fn match_class(mut pattern: &[u8], byte: u8) -> (bool, &[u8]) {
+    let negated = pattern.first() == Some(&b'^');
+    if negated {
+        pattern = &pattern[1..];
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => break,
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == byte;
+                pattern = rest;
+            }
+            [b']', rest @ ..] => {
+                pattern = rest;
+                break;
+            }
+            [start, b'-', end, rest @ ..] => {
+                let (low, high) = if start <= end {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+                matched |= (low..=high).contains(&&byte);
+                pattern = rest;
+            }
+            [other, rest @ ..] => {
+                matched |= *other == byte;
+                pattern = rest;
+            }
+        }
+    }
+
+    (matched != negated, pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn literals_and_wildcards() {
+        assert!(glob_match(b"*", b"list-max-listpack-size"));
+        assert!(glob_match(b"list-*-size", b"list-max-listpack-size"));
+        assert!(glob_match(b"list?max*", b"list-max-listpack-size"));
+        assert!(!glob_match(b"list", b"list-max-listpack-size"));
+        assert!(!glob_match(b"*-max", b"list-max-listpack-size"));
+        assert!(glob_match(b"", b""));
+        assert!(!glob_match(b"", b"a"));
+        assert!(!glob_match(b"?", b""));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(!glob_match(b"Hello", b"hello"));
+    }
+
+    #[test]
+    fn stars() {
+        // Same as Redis, a star needs at least one byte of target to run on
+        assert!(!glob_match(b"*", b""));
+        assert!(glob_match(b"a*", b"a"));
+        assert!(glob_match(b"a***", b"a"));
+        assert!(glob_match(b"***b", b"ab"));
+        assert!(glob_match(b"*a*b*c*", b"xaxbxcx"));
+        assert!(!glob_match(b"*a*b*c*", b"xaxcxbx"));
+
+        // Backtracking: the first candidates for each star are wrong
+        assert!(glob_match(b"*ab", b"aaab"));
+        assert!(glob_match(b"a*b*c", b"abbbcbc"));
+        assert!(!glob_match(b"a*b*c", b"abbbcb"));
+
+        // Stars which can't match return quickly rather than trying every split
+        let target = "a".repeat(64);
+        let pattern = "a*".repeat(32) + "b";
+        assert!(!glob_match(pattern.as_bytes(), target.as_bytes()));
+
+        // Too deeply nested to match
+        let pattern = "a*".repeat(2_000);
+        assert!(!glob_match(
+            pattern.as_bytes(),
+            "a".repeat(2_001).as_bytes()
+        ));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(glob_match(b"h[ae]llo", b"hello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo"));
+
+        // Reversed ranges are the same as the ordered ones
+        assert!(glob_match(b"[z-a]", b"m"));
+        assert!(glob_match(b"[0-9a-f]*", b"c0ffee"));
+
+        // An empty class matches nothing, unless it's negated
+        assert!(!glob_match(b"[]", b"a"));
+        assert!(!glob_match(b"[]", b""));
+        assert!(glob_match(b"[^]", b"a"));
+        assert!(!glob_match(b"a[]b", b"ab"));
+
+        // A class without ] runs to the end of the pattern
+        assert!(glob_match(b"[abc", b"b"));
+        assert!(!glob_match(b"[abc", b"bc"));
+        assert!(!glob_match(b"a[", b"ab"));
+        assert!(glob_match(b"[^", b"a"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(glob_match(b"\\*", b"*"));
+        assert!(!glob_match(b"\\*", b"a"));
+        assert!(glob_match(b"\\?", b"?"));
+        assert!(!glob_match(b"\\?", b"a"));
+        assert!(glob_match(b"\\[a]", b"[a]"));
+        assert!(glob_match(b"\\\\", b"\\"));
+        assert!(glob_match(b"a\\b", b"ab"));
+
+        // A trailing \ is taken literally
+        assert!(glob_match(b"a\\", b"a\\"));
+
+        // Inside a class
+        assert!(glob_match(b"[\\]]", b"]"));
+        assert!(glob_match(b"[\\^a]", b"^"));
+        assert!(glob_match(b"[a\\-z]", b"-"));
+        assert!(!glob_match(b"[a\\-z]", b"m"));
+    }
+}
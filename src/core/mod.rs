@@ -1,22 +1,55 @@
+pub mod command_table;
+pub(crate) mod glob;
 pub mod storage;
 
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use self::command_table::{Argument, ArgumentKind, CommandSpec};
+use self::glob::glob_match;
 use self::storage::{ListEnd, Storage, StorageError, StorageValue};
 
+// Strings can't grow beyond 512MB, same as Redis
+const STRING_MAX_SIZE: usize = 512 * 1024 * 1024;
+
+// Parameters which can be read and changed with CONFIG GET and CONFIG SET
+const CONFIG_PARAMETERS: &[&str] = &["list-max-listpack-size"];
+
 #[derive(Debug, Clone)]
 pub enum Command {
     // Generic commands
     Del(Vec<Key>),
-    Expire(Key, usize),
+    // TTL in seconds
+    Expire(Key, isize),
+    // TTL in milliseconds
+    PExpire(Key, isize),
+    // Unix time in seconds
+    ExpireAt(Key, isize),
+    // Unix time in milliseconds
+    PExpireAt(Key, isize),
+    ExpireTime(Key),
+    PExpireTime(Key),
     Ttl(Key),
+    PTtl(Key),
     Exists(Vec<Key>),
     Flush,
+    DbSize,
 
     // String commands
     Get(Key),
     Set(Key, Vec<u8>),
     SetNx(Key, Vec<u8>),
+    // SET with the NX or XX option
+    SetIf(Key, Vec<u8>, SetCondition),
+    Append(Key, Vec<u8>),
     GetSet(Key, Vec<u8>),
     GetDel(Key),
+    Strlen(Key),
+    GetRange(Key, isize, isize),
+    SetRange(Key, isize, Vec<u8>),
     MGet(Vec<Key>),
     MSet(Vec<Key>, Vec<Vec<u8>>),
     Incr(Key),
@@ -27,17 +60,158 @@ pub enum Command {
     // List commands
     LPush(Key, Vec<Vec<u8>>),
     RPush(Key, Vec<Vec<u8>>),
+    LPushX(Key, Vec<Vec<u8>>),
+    RPushX(Key, Vec<Vec<u8>>),
     LPop(Key, usize),
     RPop(Key, usize),
+    // Pop from the first non-empty list. No count means a single element
+    LMPop(Vec<Key>, ListEnd, Option<usize>),
+
+    // Connection commands
+    Quit,
+
+    // Server commands
+    Time,
+    Role,
+    // There is no replication to configure, both are rejected
+    ReplicaOf,
+    Failover,
+    // Every command processed afterwards is streamed to the connection
+    Monitor,
+    Help(HelpFamily),
+    CommandCount,
+    CommandList,
+    CommandInfo(Vec<Vec<u8>>),
+    CommandDocs(Vec<Vec<u8>>),
+    ObjectEncoding(Key),
+    Debug(DebugSubcommand),
+    // The items of a list are estimated from this many of them, 0 means all
+    MemoryUsage(Key, usize),
+    MemoryDoctor,
+    MemoryStats,
+    Info,
+    // Parameters matching any of the glob-style patterns
+    ConfigGet(Vec<Vec<u8>>),
+    ConfigSet(Vec<(Vec<u8>, Vec<u8>)>),
+    ClientNoEvict(bool),
+    ClientNoTouch(bool),
 
     // Internal commands
     ExpIntervalCheck,
 }
 
+impl Command {
+    /// The name of the command in the command table, which holds its arity,
+    /// flags and docs. None for internal commands, which clients can't send
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match self {
+            Command::Del(_) => "del",
+            Command::Expire(_, _) => "expire",
+            Command::PExpire(_, _) => "pexpire",
+            Command::ExpireAt(_, _) => "expireat",
+            Command::PExpireAt(_, _) => "pexpireat",
+            Command::ExpireTime(_) => "expiretime",
+            Command::PExpireTime(_) => "pexpiretime",
+            Command::Ttl(_) => "ttl",
+            Command::PTtl(_) => "pttl",
+            Command::Exists(_) => "exists",
+            Command::Flush => "flushall",
+            Command::DbSize => "dbsize",
+            Command::Get(_) => "get",
+            Command::Set(_, _) | Command::SetIf(_, _, _) => "set",
+            Command::SetNx(_, _) => "setnx",
+            Command::Append(_, _) => "append",
+            Command::GetSet(_, _) => "getset",
+            Command::GetDel(_) => "getdel",
+            Command::Strlen(_) => "strlen",
+            Command::GetRange(_, _, _) => "getrange",
+            Command::SetRange(_, _, _) => "setrange",
+            Command::MGet(_) => "mget",
+            Command::MSet(_, _) => "mset",
+            Command::Incr(_) => "incr",
+            Command::Decr(_) => "decr",
+            Command::IncrBy(_, _) => "incrby",
+            Command::DecrBy(_, _) => "decrby",
+            Command::LPush(_, _) => "lpush",
+            Command::RPush(_, _) => "rpush",
+            Command::LPushX(_, _) => "lpushx",
+            Command::RPushX(_, _) => "rpushx",
+            Command::LPop(_, _) => "lpop",
+            Command::RPop(_, _) => "rpop",
+            Command::LMPop(_, _, _) => "lmpop",
+            Command::Quit => "quit",
+            Command::Time => "time",
+            Command::Role => "role",
+            Command::ReplicaOf => "replicaof",
+            Command::Failover => "failover",
+            Command::Monitor => "monitor",
+            Command::Help(HelpFamily::Command)
+            | Command::CommandCount
+            | Command::CommandList
+            | Command::CommandInfo(_)
+            | Command::CommandDocs(_) => "command",
+            Command::Help(HelpFamily::Object) | Command::ObjectEncoding(_) => "object",
+            Command::Help(HelpFamily::Debug) | Command::Debug(_) => "debug",
+            Command::Help(HelpFamily::Memory)
+            | Command::MemoryUsage(_, _)
+            | Command::MemoryDoctor
+            | Command::MemoryStats => "memory",
+            Command::Help(HelpFamily::Config) | Command::ConfigGet(_) | Command::ConfigSet(_) => {
+                "config"
+            }
+            Command::Help(HelpFamily::Client)
+            | Command::ClientNoEvict(_)
+            | Command::ClientNoTouch(_) => "client",
+            Command::Info => "info",
+            Command::ExpIntervalCheck => return None,
+        };
+
+        Some(name)
+    }
+}
+
+/// Commands which group their functionalities under subcommands,
+/// e.g. COMMAND HELP or OBJECT HELP
+#[derive(Debug, Clone, PartialEq)]
+pub enum HelpFamily {
+    Command,
+    Object,
+    Debug,
+    Memory,
+    Config,
+    Client,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetCondition {
+    // Only set the key if it doesn't exist
+    Nx,
+    // Only set the key if it already exists
+    Xx,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugSubcommand {
+    Reload,
+    SetActiveExpire(bool),
+    KeyVersion(Key),
+    Object(Key),
+    // Expire the key right away, so tests don't have to wait for it
+    Expire(Key),
+    // Block the core, same as the single thread of Redis. Commands of the other
+    // connections are still parsed, they queue up behind it. Their connection
+    // threads wait for the replies without spinning
+    Sleep(Duration),
+    // Subcommands which are accepted for compatibility but have no effect
+    NoOp,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum CommandResponse<'a> {
     SimpleString(&'a [u8]),
     BulkString(Vec<u8>),
+    // Same as BulkString, for values which can be sent straight from the storage
+    BorrowedBulkString(&'a [u8]),
     Integer(isize),
     Array(Vec<CommandResponse<'a>>),
     Error(String),
@@ -45,273 +219,930 @@ pub enum CommandResponse<'a> {
     NullArray,
 }
 
+/// Errors replied to the client when a command can't be executed. The messages
+/// are the same as Redis', as clients match on them
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    WrongType,
+    NotInteger,
+    Overflow,
+    NoSuchKey,
+    SyntaxError,
+    // A negative offset of SETRANGE
+    OutOfRange,
+    // The string would grow beyond STRING_MAX_SIZE
+    StringTooLong,
+    UnknownConfigParameter(String),
+    InvalidConfigValue(String),
+    PersistenceUnsupported,
+    ReplicationDisabled,
+}
+
+impl CommandError {
+    pub fn to_response(&self) -> CommandResponse<'static> {
+        CommandResponse::Error(self.to_string())
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::WrongType => write!(
+                f,
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ),
+            CommandError::NotInteger => write!(f, "ERR value is not an integer or out of range"),
+            CommandError::Overflow => write!(f, "ERR increment or decrement would overflow"),
+            CommandError::NoSuchKey => write!(f, "ERR no such key"),
+            CommandError::SyntaxError => write!(f, "ERR syntax error"),
+            CommandError::OutOfRange => write!(f, "ERR offset is out of range"),
+            CommandError::StringTooLong => write!(
+                f,
+                "ERR string exceeds maximum allowed size (proto-max-bulk-len)"
+            ),
+            CommandError::UnknownConfigParameter(name) => write!(
+                f,
+                "ERR Unknown option or number of arguments for CONFIG SET - '{name}'"
+            ),
+            CommandError::InvalidConfigValue(name) => write!(
+                f,
+                "ERR CONFIG SET failed (possibly related to argument '{name}') - argument must be a positive integer"
+            ),
+            CommandError::PersistenceUnsupported => write!(
+                f,
+                "ERR DEBUG RELOAD requires persistence, which is not supported"
+            ),
+            CommandError::ReplicationDisabled => {
+                write!(f, "ERR This instance has replication disabled")
+            }
+        }
+    }
+}
+
+impl From<StorageError> for CommandError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::WrongOperationType => CommandError::WrongType,
+            StorageError::NotInteger => CommandError::NotInteger,
+            StorageError::Overflow => CommandError::Overflow,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Key(pub Vec<u8>);
 
 pub struct Core {
     storage: Storage,
+    // Commands waiting in the job queue, as last reported by the server
+    queue_depth: usize,
+    // Whether ExpIntervalCheck sweeps expired keys. When disabled, expired keys
+    // are only hidden from reads until they're overwritten or deleted
+    active_expire: bool,
+    // Lists are trimmed to this length on push, dropping from the opposite end
+    list_max_length: Option<usize>,
+    // For the uptime reported by INFO
+    start_time: Instant,
 }
 
 impl Core {
     pub fn new() -> Self {
         Self {
             storage: Storage::new(),
+            queue_depth: 0,
+            active_expire: true,
+            list_max_length: None,
+            start_time: Instant::now(),
         }
     }
 
+    /// The server reports when it started, so the uptime includes its startup
+    pub fn set_start_time(&mut self, start_time: Instant) {
+        self.start_time = start_time;
+    }
+
+    /// The core doesn't own the job queue, the server reports its depth
+    pub fn set_queue_depth(&mut self, queue_depth: usize) {
+        self.queue_depth = queue_depth;
+    }
+
+    pub fn set_list_max_length(&mut self, list_max_length: Option<usize>) {
+        self.list_max_length = list_max_length;
+    }
+
+    /// See Storage::set_coarse_clock. The clock ticks on every ExpIntervalCheck
+    pub fn set_coarse_clock(&mut self, enabled: bool) {
+        self.storage.set_coarse_clock(enabled);
+    }
+
+    pub fn set_list_max_listpack_size(&mut self, list_max_listpack_size: usize) {
+        self.storage
+            .set_list_max_listpack_size(list_max_listpack_size);
+    }
+
+    /// Route a command to its handler. Each arm only unpacks the command, the
+    /// logic lives in a method per command so it can be tested on its own
     pub fn handle_command(&mut self, command: Command) -> CommandResponse {
         match command {
-            Command::ExpIntervalCheck => {
-                for key in self.storage.scan_expired_keys() {
-                    if let Some(true) = self.storage.is_expire(&key) {
-                        self.storage.delete(&key);
-                    }
-                }
+            Command::ExpIntervalCheck => self.exp_interval_check(),
 
-                CommandResponse::Null
-            }
+            // Generic commands
+            Command::Del(keys) => self.del(keys),
+            Command::Expire(key, seconds) => self.expire(key, seconds.saturating_mul(1000)),
+            Command::PExpire(key, milliseconds) => self.expire(key, milliseconds),
+            Command::ExpireAt(key, seconds) => self.expire_at(key, seconds.saturating_mul(1000)),
+            Command::PExpireAt(key, milliseconds) => self.expire_at(key, milliseconds),
+            Command::ExpireTime(key) => self.expire_time(&key),
+            Command::PExpireTime(key) => CommandResponse::Integer(self.storage.expire_time(&key)),
+            Command::Ttl(key) => self.ttl(&key),
+            Command::PTtl(key) => CommandResponse::Integer(self.storage.ttl(&key)),
+            Command::Exists(keys) => self.exists(keys),
+            Command::Flush => self.flush(),
+            Command::DbSize => CommandResponse::Integer(self.storage.key_count() as isize),
 
-            Command::Del(keys) => {
-                let deleted_count = keys
-                    .into_iter()
-                    .filter(|key| self.storage.delete(key))
-                    .count();
-                CommandResponse::Integer(deleted_count as isize)
+            // String commands
+            Command::Get(key) => self.get(&key),
+            Command::Set(key, value) => self.set(key, value),
+            Command::SetNx(key, value) => self.set_nx(key, value),
+            Command::SetIf(key, value, condition) => self.set_if(key, value, condition),
+            Command::Append(key, value) => self.append(key, value),
+            Command::Strlen(key) => self.strlen(&key),
+            Command::GetRange(key, start, end) => self.getrange(&key, start, end),
+            Command::SetRange(key, offset, value) => self.setrange(key, offset, value),
+            Command::GetSet(key, value) => {
+                Core::previous_value(self.storage.get_and_set(key, value))
             }
+            Command::GetDel(key) => Core::previous_value(self.storage.get_and_delete(&key)),
+            Command::MGet(keys) => self.mget(&keys),
+            Command::MSet(keys, values) => self.mset(keys, values),
+            Command::Incr(key) => self.incr(&key, 1),
+            Command::Decr(key) => self.incr(&key, -1),
+            Command::IncrBy(key, value) => self.incr_by(&key, value, false),
+            Command::DecrBy(key, value) => self.incr_by(&key, value, true),
 
-            Command::Expire(key, ttl) => {
-                if self.storage.is_exist(&key) {
-                    let milliseconds: u64 = u64::try_from(ttl * 1000).unwrap();
-                    self.storage.expire(&key, milliseconds);
-                    CommandResponse::Integer(1)
-                } else {
-                    CommandResponse::Integer(0)
-                }
-            }
+            // List commands
+            Command::LPush(key, values) => self.push(key, values, ListEnd::Front),
+            Command::RPush(key, values) => self.push(key, values, ListEnd::Back),
+            Command::LPushX(key, values) => self.push_if_exists(key, values, ListEnd::Front),
+            Command::RPushX(key, values) => self.push_if_exists(key, values, ListEnd::Back),
+            Command::LPop(key, count) => self.pop(key, count, ListEnd::Front),
+            Command::RPop(key, count) => self.pop(key, count, ListEnd::Back),
+            Command::LMPop(keys, list_end, count) => self.lmpop(keys, list_end, count),
 
-            Command::Ttl(key) => {
-                let ttl = self.storage.ttl(&key);
-                CommandResponse::Integer(ttl)
-            }
+            // Connection commands
+            // The server closes the connection once the reply is written
+            Command::Quit => CommandResponse::SimpleString(b"OK"),
 
-            Command::Exists(keys) => {
-                let count = keys
-                    .into_iter()
-                    .filter(|key| self.storage.is_exist(key))
-                    .count();
-                CommandResponse::Integer(count as isize)
+            // Server commands
+            Command::Time => Core::time(),
+            Command::Role => Core::role(),
+            Command::ReplicaOf | Command::Failover => {
+                CommandError::ReplicationDisabled.to_response()
             }
-
-            Command::Flush => {
-                self.storage = Storage::new();
+            // The server registers the connection for the stream
+            Command::Monitor => CommandResponse::SimpleString(b"OK"),
+            Command::CommandCount => {
+                CommandResponse::Integer(command_table::COMMANDS.len() as isize)
+            }
+            Command::CommandList => Core::command_list(),
+            Command::CommandInfo(names) => Core::command_info(&names),
+            Command::CommandDocs(names) => Core::command_docs(&names),
+            Command::Help(family) => Core::help(family),
+            Command::ObjectEncoding(key) => self.object_encoding(&key),
+            Command::MemoryUsage(key, samples) => self.memory_usage(&key, samples),
+            Command::MemoryDoctor => self.memory_doctor(),
+            Command::MemoryStats => self.memory_stats(),
+            Command::Info => self.info(),
+            Command::ConfigGet(patterns) => self.config_get(&patterns),
+            Command::ConfigSet(pairs) => self.config_set(pairs),
+            // There is neither client eviction nor LRU/LFU stats, both modes have no effect
+            Command::ClientNoEvict(_) | Command::ClientNoTouch(_) => {
                 CommandResponse::SimpleString(b"OK")
             }
+            Command::Debug(subcommand) => self.debug(subcommand),
+        }
+    }
 
-            Command::Get(key) => self.get(&key),
+    fn exp_interval_check(&mut self) -> CommandResponse<'static> {
+        self.storage.tick();
+        if !self.active_expire {
+            return CommandResponse::Null;
+        }
 
-            Command::Set(key, value) => {
-                self.storage.set(key, value);
-                CommandResponse::SimpleString(b"OK")
+        for key in self.storage.scan_expired_keys() {
+            if let Some(true) = self.storage.is_expire(&key) {
+                self.storage.delete(&key);
             }
+        }
+
+        CommandResponse::Null
+    }
+
+    fn del(&mut self, keys: Vec<Key>) -> CommandResponse<'static> {
+        let deleted_count = keys
+            .into_iter()
+            .filter(|key| self.storage.delete(key))
+            .count();
+        CommandResponse::Integer(deleted_count as isize)
+    }
 
-            Command::SetNx(key, value) => match self.storage.get(&key) {
-                Ok(Some(_)) => CommandResponse::Integer(0),
-                Ok(None) => {
-                    self.storage.set(key, value);
-                    CommandResponse::Integer(1)
+    // EXPIRE and PEXPIRE, the TTL is in milliseconds. Same as Redis, a TTL
+    // which isn't positive deletes the key right away
+    fn expire(&mut self, key: Key, ttl_ms: isize) -> CommandResponse<'static> {
+        if self.storage.is_exist(&key) {
+            match u64::try_from(ttl_ms) {
+                Ok(milliseconds) if milliseconds > 0 => self.storage.expire(&key, milliseconds),
+                _ => {
+                    self.storage.delete(&key);
                 }
-                Err(error) => Core::translate_error(error),
+            }
+            CommandResponse::Integer(1)
+        } else {
+            CommandResponse::Integer(0)
+        }
+    }
+
+    // EXPIREAT and PEXPIREAT, the time is in Unix milliseconds
+    fn expire_at(&mut self, key: Key, unix_time_ms: isize) -> CommandResponse<'static> {
+        if self.storage.is_exist(&key) {
+            // A negative time is in the past too
+            let unix_time_ms = u64::try_from(unix_time_ms).unwrap_or(0);
+            self.storage.expire_at(&key, unix_time_ms);
+            CommandResponse::Integer(1)
+        } else {
+            CommandResponse::Integer(0)
+        }
+    }
+
+    fn expire_time(&self, key: &Key) -> CommandResponse<'static> {
+        match self.storage.expire_time(key) {
+            milliseconds if milliseconds >= 0 => CommandResponse::Integer(milliseconds / 1000),
+            missing_or_no_expiration => CommandResponse::Integer(missing_or_no_expiration),
+        }
+    }
+
+    fn ttl(&self, key: &Key) -> CommandResponse<'static> {
+        match self.storage.ttl(key) {
+            milliseconds if milliseconds >= 0 => CommandResponse::Integer(milliseconds / 1000),
+            missing_or_no_expiration => CommandResponse::Integer(missing_or_no_expiration),
+        }
+    }
+
+    fn exists(&self, keys: Vec<Key>) -> CommandResponse<'static> {
+        let count = keys
+            .into_iter()
+            .filter(|key| self.storage.is_exist(key))
+            .count();
+        CommandResponse::Integer(count as isize)
+    }
+
+    fn flush(&mut self) -> CommandResponse<'static> {
+        self.storage.flush();
+        CommandResponse::SimpleString(b"OK")
+    }
+
+    fn get(&self, key: &Key) -> CommandResponse<'_> {
+        match self.storage.get(key) {
+            Ok(Some(value)) => match value.as_string_bytes() {
+                Some(Cow::Borrowed(string)) => CommandResponse::BorrowedBulkString(string),
+                Some(Cow::Owned(string)) => CommandResponse::BulkString(string),
+                None => CommandError::WrongType.to_response(),
             },
+            Ok(None) => CommandResponse::Null,
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
 
-            command @ (Command::GetSet(_, _) | Command::GetDel(_)) => {
-                let operator = if matches!(command, Command::GetSet(_, _)) {
-                    "GET"
-                } else if matches!(command, Command::GetDel(_)) {
-                    "DEL"
-                } else {
-                    unreachable!();
-                };
+    fn set(&mut self, key: Key, value: Vec<u8>) -> CommandResponse<'static> {
+        self.storage.set(key, value);
+        CommandResponse::SimpleString(b"OK")
+    }
 
-                let (key, value) = match command {
-                    Command::GetSet(key, value) => (key, value),
-                    // Leave it as vec so compiler doesn't complain about ununiformed types
-                    Command::GetDel(key) => (key, vec![]),
-                    _ => unreachable!(),
-                };
+    // Replies with an integer, unlike SET NX
+    fn set_nx(&mut self, key: Key, value: Vec<u8>) -> CommandResponse<'static> {
+        if self.storage.is_exist(&key) {
+            CommandResponse::Integer(0)
+        } else {
+            self.storage.set(key, value);
+            CommandResponse::Integer(1)
+        }
+    }
 
-                match self.storage.get(&key) {
-                    Ok(Some(old_value)) => {
-                        let response = match old_value {
-                            StorageValue::String(bytes) => {
-                                CommandResponse::BulkString(bytes.to_owned())
-                            }
-                            StorageValue::Integer(integer) => {
-                                CommandResponse::BulkString(integer.to_string().into_bytes())
-                            }
-                            StorageValue::List(_) => return CommandResponse::Error(String::from(
-                                "WRONGTYPE Operation against a key holding the wrong kind of value",
-                            )),
-                        };
-
-                        // I can't find a way to extract this function to a separate closure
-                        // without pissing off the borrow checker
-                        match operator {
-                            "GET" => {
-                                self.storage.set(key, value);
-                            }
-                            "DEL" => {
-                                self.storage.delete(&key);
-                            }
-                            _ => unreachable!(),
-                        };
+    fn set_if(
+        &mut self,
+        key: Key,
+        value: Vec<u8>,
+        condition: SetCondition,
+    ) -> CommandResponse<'static> {
+        let exists = self.storage.is_exist(&key);
+        let should_set = match condition {
+            SetCondition::Nx => !exists,
+            SetCondition::Xx => exists,
+        };
 
-                        response
-                    }
-                    Ok(None) => {
-                        match operator {
-                            "GET" => {
-                                self.storage.set(key, value);
-                            }
-                            "DEL" => {
-                                self.storage.delete(&key);
-                            }
-                            _ => unreachable!(),
-                        };
+        if should_set {
+            self.storage.set(key, value);
+            CommandResponse::SimpleString(b"OK")
+        } else {
+            CommandResponse::Null
+        }
+    }
 
-                        CommandResponse::Null
-                    }
-                    Err(error) => Core::translate_error(error),
-                }
-            }
+    fn append(&mut self, key: Key, value: Vec<u8>) -> CommandResponse<'static> {
+        match self.storage.append(key, value) {
+            Ok(len) => CommandResponse::Integer(len as isize),
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
 
-            Command::MGet(keys) => {
-                let values = keys
-                    .iter()
-                    .map(|key| self.get(&key))
-                    .collect::<Vec<CommandResponse>>();
+    fn strlen(&self, key: &Key) -> CommandResponse<'static> {
+        match self.storage.strlen(key) {
+            Ok(len) => CommandResponse::Integer(len as isize),
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
 
-                CommandResponse::Array(values)
-            }
+    fn getrange(&self, key: &Key, start: isize, end: isize) -> CommandResponse<'static> {
+        match self.storage.getrange(key, start, end) {
+            Ok(string) => CommandResponse::BulkString(string),
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
 
-            Command::MSet(keys, mut values) => {
-                keys.into_iter().for_each(|key| {
-                    let value = values.remove(0);
-                    self.storage.set(key, value);
-                });
+    fn setrange(&mut self, key: Key, offset: isize, value: Vec<u8>) -> CommandResponse<'static> {
+        if offset < 0 {
+            return CommandError::OutOfRange.to_response();
+        }
 
-                CommandResponse::SimpleString(b"OK")
-            }
+        // Same limit as Redis' proto-max-bulk-len
+        if offset as usize + value.len() > STRING_MAX_SIZE {
+            return CommandError::StringTooLong.to_response();
+        }
 
-            command @ (Command::Incr(_) | Command::Decr(_)) => match command {
-                Command::Incr(key) => match self.storage.incr(&key, 1) {
-                    Ok(new_value) => CommandResponse::Integer(new_value),
-                    Err(error) => Core::translate_error(error),
-                },
-                Command::Decr(key) => match self.storage.incr(&key, -1) {
-                    Ok(new_value) => CommandResponse::Integer(new_value),
-                    Err(error) => Core::translate_error(error),
-                },
-                _ => unreachable!(),
-            },
+        match self.storage.setrange(key, offset as usize, value) {
+            Ok(len) => CommandResponse::Integer(len as isize),
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
 
-            command @ (Command::IncrBy(_, _) | Command::DecrBy(_, _)) => {
-                let negative = if matches!(command, Command::IncrBy(_, _)) {
-                    false
-                } else if matches!(command, Command::DecrBy(_, _)) {
-                    true
-                } else {
-                    unreachable!()
-                };
+    // Unlike GET, a key holding another type is nil rather than an error
+    fn mget(&self, keys: &[Key]) -> CommandResponse<'_> {
+        let values = keys
+            .iter()
+            .map(|key| match self.get(key) {
+                CommandResponse::Error(_) => CommandResponse::Null,
+                value => value,
+            })
+            .collect::<Vec<CommandResponse>>();
 
-                let (key, value) = match command {
-                    Command::IncrBy(key, value) => (key, value),
-                    Command::DecrBy(key, value) => (key, value),
-                    _ => unreachable!(),
-                };
+        CommandResponse::Array(values)
+    }
 
-                match String::from_utf8(value)
-                    .ok()
-                    .and_then(|string| string.parse::<isize>().ok())
-                {
-                    Some(integer) => {
-                        let value = if negative { -integer } else { integer };
+    fn mset(&mut self, keys: Vec<Key>, values: Vec<Vec<u8>>) -> CommandResponse<'static> {
+        for (key, value) in keys.into_iter().zip(values) {
+            self.storage.set(key, value);
+        }
 
-                        match self.storage.incr(&key, value) {
-                            Ok(new_value) => CommandResponse::Integer(new_value),
-                            Err(error) => Core::translate_error(error),
-                        }
+        CommandResponse::SimpleString(b"OK")
+    }
+
+    fn incr(&mut self, key: &Key, inc: isize) -> CommandResponse<'static> {
+        match self.storage.incr(key, inc) {
+            Ok(new_value) => CommandResponse::Integer(new_value),
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
+
+    // INCRBY and DECRBY, whose increment is only parsed here
+    fn incr_by(&mut self, key: &Key, value: Vec<u8>, negative: bool) -> CommandResponse<'static> {
+        match String::from_utf8(value)
+            .ok()
+            .and_then(|string| string.parse::<isize>().ok())
+        {
+            Some(integer) => {
+                let value = if negative {
+                    match integer.checked_neg() {
+                        Some(value) => value,
+                        None => return CommandError::Overflow.to_response(),
                     }
-                    None => CommandResponse::Error(String::from(
-                        "ERR value is not an integer or out of range",
-                    )),
-                }
+                } else {
+                    integer
+                };
+
+                self.incr(key, value)
             }
+            None => CommandError::NotInteger.to_response(),
+        }
+    }
 
-            Command::LPush(key, values) => match self.storage.push(key, values, ListEnd::Front) {
-                Ok(size) => CommandResponse::Integer(size as isize),
-                Err(error) => Core::translate_error(error),
-            },
+    // LPUSHX and RPUSHX
+    fn push_if_exists(
+        &mut self,
+        key: Key,
+        values: Vec<Vec<u8>>,
+        list_end: ListEnd,
+    ) -> CommandResponse<'static> {
+        if self.storage.is_exist(&key) {
+            self.push(key, values, list_end)
+        } else {
+            CommandResponse::Integer(0)
+        }
+    }
 
-            Command::RPush(key, values) => match self.storage.push(key, values, ListEnd::Back) {
-                Ok(size) => CommandResponse::Integer(size as isize),
-                Err(error) => Core::translate_error(error),
-            },
+    fn pop(&mut self, key: Key, count: usize, list_end: ListEnd) -> CommandResponse<'static> {
+        match self.storage.pop(key, count, list_end) {
+            Ok(None) => CommandResponse::Null,
+            Ok(Some(values)) => {
+                let items = values
+                    .into_iter()
+                    .map(CommandResponse::BulkString)
+                    .collect::<Vec<_>>();
+                CommandResponse::Array(items)
+            }
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
 
-            Command::LPop(key, count) => match self.storage.pop(key, count, ListEnd::Front) {
-                Ok(None) => CommandResponse::Null,
+    fn lmpop(
+        &mut self,
+        keys: Vec<Key>,
+        list_end: ListEnd,
+        count: Option<usize>,
+    ) -> CommandResponse<'static> {
+        for key in keys {
+            match self.storage.pop(key.clone(), count.unwrap_or(1), list_end) {
+                Ok(None) => continue,
                 Ok(Some(values)) => {
                     let items = values
                         .into_iter()
-                        .map(|item| CommandResponse::BulkString(item))
+                        .map(CommandResponse::BulkString)
                         .collect::<Vec<_>>();
-                    CommandResponse::Array(items)
-                }
-                Err(error) => Core::translate_error(error),
-            },
 
-            Command::RPop(key, count) => match self.storage.pop(key, count, ListEnd::Back) {
-                Ok(None) => CommandResponse::Null,
-                Ok(Some(values)) => {
-                    let items = values
-                        .into_iter()
-                        .map(|item| CommandResponse::BulkString(item))
-                        .collect::<Vec<_>>();
-                    CommandResponse::Array(items)
+                    return CommandResponse::Array(vec![
+                        CommandResponse::BulkString(key.0),
+                        CommandResponse::Array(items),
+                    ]);
                 }
-                Err(error) => Core::translate_error(error),
-            },
+                Err(error) => return CommandError::from(error).to_response(),
+            }
+        }
+
+        CommandResponse::NullArray
+    }
+
+    fn time() -> CommandResponse<'static> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock is set before Unix epoch");
+
+        CommandResponse::Array(vec![
+            CommandResponse::BulkString(now.as_secs().to_string().into_bytes()),
+            CommandResponse::BulkString(now.subsec_micros().to_string().into_bytes()),
+        ])
+    }
+
+    // Always a master without replicas, there is no replication
+    fn role() -> CommandResponse<'static> {
+        CommandResponse::Array(vec![
+            CommandResponse::BulkString(b"master".to_vec()),
+            CommandResponse::Integer(0),
+            CommandResponse::Array(vec![]),
+        ])
+    }
+
+    fn command_list() -> CommandResponse<'static> {
+        let names = command_table::COMMANDS
+            .iter()
+            .map(|spec| CommandResponse::BulkString(spec.name.as_bytes().to_vec()))
+            .collect();
+        CommandResponse::Array(names)
+    }
+
+    fn command_info(names: &[Vec<u8>]) -> CommandResponse<'static> {
+        let specs: Vec<Option<&CommandSpec>> = if names.is_empty() {
+            command_table::COMMANDS.iter().map(Some).collect()
+        } else {
+            names.iter().map(|name| command_table::find(name)).collect()
+        };
+
+        let items = specs
+            .into_iter()
+            .map(|spec| match spec {
+                Some(spec) => Core::spec_info(spec),
+                None => CommandResponse::NullArray,
+            })
+            .collect();
+        CommandResponse::Array(items)
+    }
+
+    // Unlike COMMAND INFO, unknown commands are left out
+    fn command_docs(names: &[Vec<u8>]) -> CommandResponse<'static> {
+        let specs: Vec<&CommandSpec> = if names.is_empty() {
+            command_table::COMMANDS.iter().collect()
+        } else {
+            names
+                .iter()
+                .filter_map(|name| command_table::find(name))
+                .collect()
+        };
+
+        let items = specs
+            .into_iter()
+            .flat_map(|spec| {
+                [
+                    CommandResponse::BulkString(spec.name.as_bytes().to_vec()),
+                    Core::spec_docs(spec),
+                ]
+            })
+            .collect();
+        CommandResponse::Array(items)
+    }
+
+    fn help(family: HelpFamily) -> CommandResponse<'static> {
+        let lines: &[&'static [u8]] = match family {
+            HelpFamily::Command => &[
+                b"COMMAND <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                b"COUNT",
+                b"    Return the total number of commands in this server.",
+                b"LIST",
+                b"    Return a list of all commands in this server.",
+                b"INFO [<command-name> ...]",
+                b"    Return details about the given commands, or all of them if none is given.",
+                b"DOCS [<command-name> ...]",
+                b"    Return documentation details about the given commands, or all of them if",
+                b"    none is given.",
+                b"HELP",
+                b"    Print this help.",
+            ],
+            HelpFamily::Object => &[
+                b"OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                b"ENCODING <key>",
+                b"    Return the kind of internal representation used in order to store the value",
+                b"    associated with a <key>.",
+                b"HELP",
+                b"    Print this help.",
+            ],
+            HelpFamily::Memory => &[
+                b"MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                b"DOCTOR",
+                b"    Return memory problems reports.",
+                b"STATS",
+                b"    Return information about the memory usage of the server.",
+                b"USAGE <key> [SAMPLES <count>]",
+                b"    Return memory in bytes used by <key> and its value. Nested values are",
+                b"    sampled up to <count> times (default: 5, 0 means sample all).",
+                b"HELP",
+                b"    Print this help.",
+            ],
+            HelpFamily::Config => &[
+                b"CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                b"GET <pattern>",
+                b"    Return parameters matching the glob-like <pattern> and their values.",
+                b"SET <directive> <value>",
+                b"    Set the configuration <directive> to <value>.",
+                b"HELP",
+                b"    Print this help.",
+            ],
+            HelpFamily::Client => &[
+                b"CLIENT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                b"NO-EVICT (ON|OFF)",
+                b"    Protect current client connection from eviction.",
+                b"NO-TOUCH (ON|OFF)",
+                b"    Will not touch LRU/LFU stats when this mode is on.",
+                b"HELP",
+                b"    Print this help.",
+            ],
+            HelpFamily::Debug => &[
+                b"DEBUG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                b"OBJECT <key>",
+                b"    Show low level info about the <key> and associated value.",
+                b"RELOAD",
+                b"    Save the dataset on disk and reload it back to memory. Not supported",
+                b"    since there is no persistence.",
+                b"SET-ACTIVE-EXPIRE <0|1>",
+                b"    Setting it to 0 disables expiring keys in background when they are not",
+                b"    accessed (otherwise the Redis behavior). Setting it to 1 reenables back the",
+                b"    default.",
+                b"EXPIRE <key>",
+                b"    Expire <key> right away, as if its TTL had run out.",
+                b"KEYVERSION <key>",
+                b"    Return the version of <key>, which changes every time the key is",
+                b"    modified. 0 if the key doesn't exist.",
+                b"SLEEP <seconds>",
+                b"    Stop the server for <seconds>. Decimals allowed.",
+                b"QUICKLIST-PACKED-THRESHOLD <size>",
+                b"    Accepted for compatibility, has no effect.",
+                b"STRINGMATCH-LEN <len>",
+                b"    Accepted for compatibility, has no effect.",
+                b"CHANGE-REPL-ID",
+                b"    Accepted for compatibility, has no effect since there is no replication.",
+                b"HELP",
+                b"    Print this help.",
+            ],
+        };
+
+        let lines = lines
+            .iter()
+            .map(|line| CommandResponse::SimpleString(line))
+            .collect();
+        CommandResponse::Array(lines)
+    }
+
+    fn object_encoding(&self, key: &Key) -> CommandResponse<'static> {
+        match self.storage.encoding(key) {
+            Some(encoding) => CommandResponse::BulkString(encoding.as_bytes().to_vec()),
+            None => CommandResponse::Null,
+        }
+    }
+
+    fn memory_usage(&self, key: &Key, samples: usize) -> CommandResponse<'static> {
+        match self.storage.memory_usage(key, samples) {
+            Some(bytes) => CommandResponse::Integer(bytes as isize),
+            None => CommandResponse::Null,
+        }
+    }
+
+    fn memory_doctor(&self) -> CommandResponse<'static> {
+        let report: &[u8] = if self.storage.memory_stats().0 == 0 {
+            b"Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions."
+        } else {
+            b"Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base."
+        };
+
+        CommandResponse::BulkString(report.to_vec())
+    }
+
+    fn memory_stats(&self) -> CommandResponse<'static> {
+        let (keys, bytes) = self.storage.memory_stats();
+        let bytes_per_key = bytes.checked_div(keys).unwrap_or(0);
+
+        let stats = [
+            ("keys.count", keys),
+            ("keys.bytes-per-key", bytes_per_key),
+            ("dataset.bytes", bytes),
+        ];
+
+        let items = stats
+            .into_iter()
+            .flat_map(|(name, value)| {
+                [
+                    CommandResponse::BulkString(name.as_bytes().to_vec()),
+                    CommandResponse::Integer(value as isize),
+                ]
+            })
+            .collect();
+        CommandResponse::Array(items)
+    }
+
+    fn info(&self) -> CommandResponse<'static> {
+        let uptime = self.start_time.elapsed().as_secs();
+        // The dataset plus the core itself. There is no allocator introspection,
+        // hence no fragmentation, and no memory limit
+        let used_memory = self.storage.memory_stats().1 + mem::size_of::<Core>();
+        // Commands are never blocking, hence no blocked clients
+        let mut info = format!(
+            "# Server\r\nuptime_in_seconds:{}\r\nuptime_in_days:{}\r\n\r\n\
+             # Clients\r\nblocked_clients:0\r\n\r\n\
+             # Memory\r\nused_memory:{}\r\nused_memory_human:{}\r\n\
+             mem_fragmentation_ratio:1.00\r\nmaxmemory:0\r\n\r\n\
+             # Stats\r\nqueue_depth:{}\r\n\r\n\
+             # Keyspace\r\n",
+            uptime,
+            uptime / (24 * 60 * 60),
+            used_memory,
+            Core::human_bytes(used_memory),
+            self.queue_depth
+        );
+
+        // Same as Redis, an empty database is left out. avg_ttl is an estimate
+        // Redis makes while sweeping, which isn't tracked here
+        let (keys, volatile_keys) = self.storage.stats();
+        if keys > 0 {
+            info += &format!("db0:keys={keys},expires={volatile_keys},avg_ttl=0\r\n");
         }
+
+        CommandResponse::BulkString(info.into_bytes())
+    }
+
+    fn config_get(&self, patterns: &[Vec<u8>]) -> CommandResponse<'static> {
+        let items = CONFIG_PARAMETERS
+            .iter()
+            .filter(|name| {
+                patterns
+                    .iter()
+                    .any(|pattern| glob_match(&pattern.to_ascii_lowercase(), name.as_bytes()))
+            })
+            .flat_map(|name| {
+                [
+                    CommandResponse::BulkString(name.as_bytes().to_vec()),
+                    CommandResponse::BulkString(self.config_value(name).into_bytes()),
+                ]
+            })
+            .collect();
+        CommandResponse::Array(items)
     }
 
-    fn get(&self, key: &Key) -> CommandResponse {
-        match self.storage.get(&key) {
-            Ok(Some(StorageValue::String(value_string))) => {
-                CommandResponse::SimpleString(value_string)
+    // Either every parameter is set or none of them
+    fn config_set(&mut self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> CommandResponse<'static> {
+        let mut values = vec![];
+        for (name, value) in pairs {
+            let name = String::from_utf8_lossy(&name).to_lowercase();
+            if !CONFIG_PARAMETERS.contains(&name.as_str()) {
+                return CommandError::UnknownConfigParameter(name).to_response();
             }
-            Ok(Some(StorageValue::Integer(integer))) => CommandResponse::Integer(integer.clone()),
-            Ok(Some(StorageValue::List(_))) => CommandResponse::Error(String::from(
-                "WRONGTYPE Operation against a key holding the wrong kind of value",
-            )),
-            Ok(None) => CommandResponse::Null,
-            Err(error) => Core::translate_error(error),
+
+            match std::str::from_utf8(&value)
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|value| *value > 0)
+            {
+                Some(value) => values.push((name, value)),
+                None => return CommandError::InvalidConfigValue(name).to_response(),
+            }
+        }
+
+        for (name, value) in values {
+            self.set_config_value(&name, value);
         }
+
+        CommandResponse::SimpleString(b"OK")
     }
 
-    fn translate_error(error: StorageError) -> CommandResponse<'static> {
-        match error {
-            StorageError::WrongOperationType => CommandResponse::Error(String::from(
-                "WRONGTYPE Operation against a key holding the wrong kind of value",
-            )),
-            StorageError::NotInteger => {
-                CommandResponse::Error(String::from("ERR value is not an integer or out of range"))
+    fn debug(&mut self, subcommand: DebugSubcommand) -> CommandResponse<'static> {
+        match subcommand {
+            // There is no persistence to save to and reload from
+            DebugSubcommand::Reload => CommandError::PersistenceUnsupported.to_response(),
+            DebugSubcommand::SetActiveExpire(enabled) => {
+                self.active_expire = enabled;
+                CommandResponse::SimpleString(b"OK")
+            }
+            DebugSubcommand::Object(key) => match self.storage.debug_object(&key) {
+                Some(details) => CommandResponse::BulkString(details.into_bytes()),
+                None => CommandError::NoSuchKey.to_response(),
+            },
+            DebugSubcommand::KeyVersion(key) => {
+                CommandResponse::Integer(self.storage.version(&key) as isize)
+            }
+            DebugSubcommand::Expire(key) => {
+                if self.storage.delete(&key) {
+                    CommandResponse::SimpleString(b"OK")
+                } else {
+                    CommandError::NoSuchKey.to_response()
+                }
             }
+            DebugSubcommand::Sleep(duration) => {
+                thread::sleep(duration);
+                CommandResponse::SimpleString(b"OK")
+            }
+            DebugSubcommand::NoOp => CommandResponse::SimpleString(b"OK"),
+        }
+    }
+
+    // Field/value pairs, as RESP2 has no map type
+    fn spec_docs(spec: &CommandSpec) -> CommandResponse<'static> {
+        CommandResponse::Array(vec![
+            CommandResponse::BulkString(b"summary".to_vec()),
+            CommandResponse::BulkString(spec.summary.as_bytes().to_vec()),
+            CommandResponse::BulkString(b"arity".to_vec()),
+            CommandResponse::Integer(spec.arity),
+            CommandResponse::BulkString(b"arguments".to_vec()),
+            Core::arguments_docs(spec.arguments),
+        ])
+    }
+
+    fn arguments_docs(arguments: &[Argument]) -> CommandResponse<'static> {
+        let docs = arguments
+            .iter()
+            .map(|argument| {
+                let mut fields = vec![
+                    CommandResponse::BulkString(b"name".to_vec()),
+                    CommandResponse::BulkString(argument.name.as_bytes().to_vec()),
+                    CommandResponse::BulkString(b"type".to_vec()),
+                    CommandResponse::BulkString(argument.kind.name().as_bytes().to_vec()),
+                ];
+
+                if let Some(token) = argument.token {
+                    fields.push(CommandResponse::BulkString(b"token".to_vec()));
+                    fields.push(CommandResponse::BulkString(token.as_bytes().to_vec()));
+                }
+
+                let mut flags = vec![];
+                if argument.optional {
+                    flags.push(CommandResponse::SimpleString(b"optional"));
+                }
+                if argument.multiple {
+                    flags.push(CommandResponse::SimpleString(b"multiple"));
+                }
+                if !flags.is_empty() {
+                    fields.push(CommandResponse::BulkString(b"flags".to_vec()));
+                    fields.push(CommandResponse::Array(flags));
+                }
+
+                if let ArgumentKind::OneOf(arguments) | ArgumentKind::Block(arguments) =
+                    argument.kind
+                {
+                    fields.push(CommandResponse::BulkString(b"arguments".to_vec()));
+                    fields.push(Core::arguments_docs(arguments));
+                }
+
+                CommandResponse::Array(fields)
+            })
+            .collect();
+
+        CommandResponse::Array(docs)
+    }
+
+    // [name, arity, flags, first key, last key, step]
+    fn spec_info(spec: &CommandSpec) -> CommandResponse<'static> {
+        let flags = spec
+            .flags
+            .iter()
+            .map(|flag| CommandResponse::SimpleString(flag.as_bytes()))
+            .collect();
+
+        CommandResponse::Array(vec![
+            CommandResponse::BulkString(spec.name.as_bytes().to_vec()),
+            CommandResponse::Integer(spec.arity),
+            CommandResponse::Array(flags),
+            CommandResponse::Integer(spec.first_key),
+            CommandResponse::Integer(spec.last_key),
+            CommandResponse::Integer(spec.step),
+        ])
+    }
+
+    fn push(
+        &mut self,
+        key: Key,
+        values: Vec<Vec<u8>>,
+        list_end: ListEnd,
+    ) -> CommandResponse<'static> {
+        let result = match self.list_max_length {
+            Some(max) => self.storage.push_capped(key, values, list_end, max),
+            None => self.storage.push(key, values, list_end),
+        };
+
+        match result {
+            Ok(size) => CommandResponse::Integer(size as isize),
+            Err(error) => CommandError::from(error).to_response(),
+        }
+    }
+
+    fn config_value(&self, name: &str) -> String {
+        match name {
+            "list-max-listpack-size" => self.storage.list_max_listpack_size().to_string(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_config_value(&mut self, name: &str, value: usize) {
+        match name {
+            "list-max-listpack-size" => self.storage.set_list_max_listpack_size(value),
+            _ => unreachable!(),
+        }
+    }
+
+    // Same as Redis, e.g. 512B, 1.50K or 2.00M
+    fn human_bytes(bytes: usize) -> String {
+        const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+        if bytes < 1024 {
+            return format!("{bytes}B");
+        }
+
+        let mut value = bytes as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        format!("{value:.2}{}", UNITS[unit])
+    }
+
+    // The reply of GETSET and GETDEL
+    fn previous_value(
+        result: Result<Option<StorageValue>, StorageError>,
+    ) -> CommandResponse<'static> {
+        match result {
+            Ok(Some(value)) => match value.as_string_bytes() {
+                Some(bytes) => CommandResponse::BulkString(bytes.into_owned()),
+                None => CommandError::WrongType.to_response(),
+            },
+            Ok(None) => CommandResponse::Null,
+            Err(error) => CommandError::from(error).to_response(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Command, CommandResponse, Core, Key};
+    use super::{
+        command_table, Command, CommandError, CommandResponse, Core, DebugSubcommand, HelpFamily,
+        Key, ListEnd, SetCondition, StorageError, STRING_MAX_SIZE,
+    };
+    use crate::connection::inbound::parse_commands;
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[test]
     fn it_works() {
@@ -326,16 +1157,1233 @@ mod tests {
 
         let command = Command::Get(key("key"));
         let response = core.handle_command(command);
-        assert_eq!(response, CommandResponse::SimpleString(b"123"));
+        assert_eq!(response, CommandResponse::BorrowedBulkString(b"123"));
 
         let command = Command::GetSet(key("key"), string("456"));
         let response = core.handle_command(command);
         assert_eq!(response, CommandResponse::BulkString(b"123".to_vec()));
     }
 
-    fn assert_response_ok(response: CommandResponse) {
-        let ok_response = CommandResponse::SimpleString(b"OK");
-        assert_eq!(response, ok_response);
+    #[test]
+    fn role() {
+        let mut core = Core::new();
+        let response = core.handle_command(Command::Role);
+
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"master".to_vec()),
+                CommandResponse::Integer(0),
+                CommandResponse::Array(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn time() {
+        let mut core = Core::new();
+        let response = core.handle_command(Command::Time);
+
+        if let CommandResponse::Array(items) = response {
+            assert_eq!(items.len(), 2);
+
+            let seconds = match &items[0] {
+                CommandResponse::BulkString(bytes) => String::from_utf8(bytes.clone())
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap(),
+                _ => panic!("Expect seconds as bulk string"),
+            };
+            // 2020-01-01T00:00:00Z
+            assert!(seconds > 1_577_836_800);
+
+            let micros = match &items[1] {
+                CommandResponse::BulkString(bytes) => String::from_utf8(bytes.clone())
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap(),
+                _ => panic!("Expect microseconds as bulk string"),
+            };
+            assert!(micros < 1_000_000);
+        } else {
+            panic!("Expect TIME to return an array");
+        }
+    }
+
+    #[test]
+    fn command_help() {
+        let mut core = Core::new();
+        let response = core.handle_command(Command::Help(HelpFamily::Command));
+
+        if let CommandResponse::Array(lines) = response {
+            assert!(!lines.is_empty());
+            assert!(lines
+                .iter()
+                .all(|line| matches!(line, CommandResponse::SimpleString(_))));
+        } else {
+            panic!("Expect COMMAND HELP to return an array");
+        }
+    }
+
+    #[test]
+    fn exists() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("key"), string("123")));
+
+        // Every occurrence of a key is counted
+        let command = Command::Exists(vec![key("key"), key("key"), key("missing")]);
+        let response = core.handle_command(command);
+        assert_eq!(response, CommandResponse::Integer(2));
+
+        // Expired keys are absent even if they haven't been swept yet
+        core.handle_command(Command::PExpire(key("key"), 1));
+        thread::sleep(Duration::from_millis(2));
+        let response = core.handle_command(Command::Exists(vec![key("key")]));
+        assert_eq!(response, CommandResponse::Integer(0));
+    }
+
+    #[test]
+    fn in_place_mutations_keep_ttl() {
+        let mut core = Core::new();
+        let pttl = |core: &mut Core| match core.handle_command(Command::PTtl(key("key"))) {
+            CommandResponse::Integer(pttl) => pttl,
+            response => panic!("Unexpected response {response:?}"),
+        };
+
+        core.handle_command(Command::Set(key("key"), string("1")));
+        core.handle_command(Command::Expire(key("key"), 100));
+
+        core.handle_command(Command::Append(key("key"), string("0")));
+        assert!(pttl(&mut core) > 0);
+
+        core.handle_command(Command::SetRange(key("key"), 2, string("0")));
+        assert!(pttl(&mut core) > 0);
+
+        let response = core.handle_command(Command::Incr(key("key")));
+        assert_eq!(response, CommandResponse::Integer(101));
+        assert!(pttl(&mut core) > 0);
+
+        // Only replacing the value resets the expiration
+        core.handle_command(Command::Set(key("key"), string("1")));
+        assert_eq!(pttl(&mut core), -1);
+    }
+
+    #[test]
+    fn mget_wrong_type() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("string"), string("value")));
+        core.handle_command(Command::LPush(key("list"), vec![string("a")]));
+
+        let command = Command::MGet(vec![key("string"), key("missing"), key("list")]);
+        let response = core.handle_command(command);
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BorrowedBulkString(b"value"),
+                CommandResponse::Null,
+                CommandResponse::Null,
+            ])
+        );
+    }
+
+    #[test]
+    fn set_replaces_any_type() {
+        let mut core = Core::new();
+        core.handle_command(Command::LPush(key("key"), vec![string("a")]));
+        core.handle_command(Command::Expire(key("key"), 100));
+
+        // Unlike the commands which modify a string, SET doesn't check the type
+        let response = core.handle_command(Command::Set(key("key"), string("123")));
+        assert_response_ok(response);
+
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::BorrowedBulkString(b"123"));
+        let response = core.handle_command(Command::PTtl(key("key")));
+        assert_eq!(response, CommandResponse::Integer(-1));
+    }
+
+    #[test]
+    fn del_expired_key() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("key"), string("123")));
+        core.handle_command(Command::Set(key("expired"), string("123")));
+        core.handle_command(Command::PExpire(key("expired"), 1));
+        thread::sleep(Duration::from_millis(2));
+
+        // The expired key is removed but isn't counted
+        let command = Command::Del(vec![key("key"), key("expired"), key("missing")]);
+        let response = core.handle_command(command);
+        assert_eq!(response, CommandResponse::Integer(1));
+        assert_eq!(
+            core.handle_command(Command::DbSize),
+            CommandResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn expire_at_and_expire_time() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("key"), string("123")));
+
+        let response = core.handle_command(Command::ExpireTime(key("key")));
+        assert_eq!(response, CommandResponse::Integer(-1));
+        let response = core.handle_command(Command::PExpireTime(key("missing")));
+        assert_eq!(response, CommandResponse::Integer(-2));
+
+        // An hour from now, with a milliseconds part
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let timestamp = (now.as_secs() + 3600) * 1000 + 123;
+
+        let command = Command::PExpireAt(key("key"), timestamp as isize);
+        assert_eq!(core.handle_command(command), CommandResponse::Integer(1));
+
+        let response = core.handle_command(Command::ExpireTime(key("key")));
+        assert_eq!(
+            response,
+            CommandResponse::Integer(timestamp as isize / 1000)
+        );
+        let response = core.handle_command(Command::PExpireTime(key("key")));
+        assert_eq!(response, CommandResponse::Integer(timestamp as isize));
+        let response = core.handle_command(Command::Ttl(key("key")));
+        assert!(matches!(response, CommandResponse::Integer(3599 | 3600)));
+
+        let command = Command::ExpireAt(key("missing"), now.as_secs() as isize + 10);
+        assert_eq!(core.handle_command(command), CommandResponse::Integer(0));
+
+        // A time in the past deletes the key
+        let command = Command::ExpireAt(key("key"), now.as_secs() as isize - 10);
+        assert_eq!(core.handle_command(command), CommandResponse::Integer(1));
+        let response = core.handle_command(Command::DbSize);
+        assert_eq!(response, CommandResponse::Integer(0));
+    }
+
+    #[test]
+    fn expire_expired_key() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("key"), string("123")));
+
+        let response = core.handle_command(Command::Expire(key("key"), 1));
+        assert_eq!(response, CommandResponse::Integer(1));
+
+        // Wait past the TTL without running the expiration sweep
+        thread::sleep(Duration::from_millis(1_100));
+
+        let response = core.handle_command(Command::Exists(vec![key("key")]));
+        assert_eq!(response, CommandResponse::Integer(0));
+
+        let response = core.handle_command(Command::Expire(key("key"), 10));
+        assert_eq!(response, CommandResponse::Integer(0));
+
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::Null);
+    }
+
+    #[test]
+    fn expire_non_positive_ttl() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("a"), string("1")));
+        core.handle_command(Command::Set(key("b"), string("1")));
+
+        // The keys are deleted right away, not left to the expiration sweep
+        let response = core.handle_command(Command::Expire(key("a"), 0));
+        assert_eq!(response, CommandResponse::Integer(1));
+        let response = core.handle_command(Command::Get(key("a")));
+        assert_eq!(response, CommandResponse::Null);
+        let response = core.handle_command(Command::DbSize);
+        assert_eq!(response, CommandResponse::Integer(1));
+
+        let response = core.handle_command(Command::PExpire(key("b"), -100));
+        assert_eq!(response, CommandResponse::Integer(1));
+        let response = core.handle_command(Command::DbSize);
+        assert_eq!(response, CommandResponse::Integer(0));
+
+        let response = core.handle_command(Command::Expire(key("a"), 0));
+        assert_eq!(response, CommandResponse::Integer(0));
+    }
+
+    #[test]
+    fn coarse_clock_ticks_on_interval_check() {
+        let mut core = Core::new();
+        core.set_coarse_clock(true);
+        let disable = Command::Debug(DebugSubcommand::SetActiveExpire(false));
+        assert_response_ok(core.handle_command(disable));
+
+        core.handle_command(Command::Set(key("key"), string("123")));
+        core.storage.expire(&key("key"), 50);
+        thread::sleep(Duration::from_millis(100));
+
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::BorrowedBulkString(b"123"));
+
+        // The clock ticks even though the expiration sweep is disabled
+        core.handle_command(Command::ExpIntervalCheck);
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::Null);
+    }
+
+    #[test]
+    fn get_wrong_type() {
+        let mut core = Core::new();
+        let wrong_type = CommandResponse::Error(String::from(
+            "WRONGTYPE Operation against a key holding the wrong kind of value",
+        ));
+
+        core.execute_str("RPUSH list a");
+        assert_eq!(core.execute_str("GET list"), wrong_type);
+    }
+
+    #[test]
+    fn get_integer() {
+        let mut core = Core::new();
+        core.handle_command(Command::Incr(key("key")));
+
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::BulkString(b"1".to_vec()));
+
+        let response = core.handle_command(Command::MGet(vec![key("key")]));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![CommandResponse::BulkString(b"1".to_vec())])
+        );
+    }
+
+    #[test]
+    fn incr_by_decr_by() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::IncrBy(key("key"), string("5")));
+        assert_eq!(response, CommandResponse::Integer(5));
+
+        let response = core.handle_command(Command::DecrBy(key("key"), string("7")));
+        assert_eq!(response, CommandResponse::Integer(-2));
+
+        let response = core.handle_command(Command::IncrBy(key("key"), string("1.5")));
+        assert_eq!(
+            response,
+            CommandResponse::Error(String::from("ERR value is not an integer or out of range"))
+        );
+    }
+
+    #[test]
+    fn incr_errors() {
+        let mut core = Core::new();
+        core.handle_command(Command::LPush(key("list"), vec![string("1")]));
+        core.handle_command(Command::Set(key("string"), string("abc")));
+
+        // Only a string can hold a number, whatever the elements of a list are
+        let response = core.handle_command(Command::Incr(key("list")));
+        assert_eq!(response, CommandError::WrongType.to_response());
+        let response = core.handle_command(Command::IncrBy(key("list"), string("2")));
+        assert_eq!(response, CommandError::WrongType.to_response());
+
+        let response = core.handle_command(Command::Incr(key("string")));
+        assert_eq!(response, CommandError::NotInteger.to_response());
+        let response = core.handle_command(Command::Decr(key("string")));
+        assert_eq!(response, CommandError::NotInteger.to_response());
+    }
+
+    #[test]
+    fn incr_by_decr_by_overflow() {
+        let mut core = Core::new();
+        let overflow_error =
+            CommandResponse::Error(String::from("ERR increment or decrement would overflow"));
+
+        let max = isize::MAX.to_string();
+        core.handle_command(Command::IncrBy(key("key"), string(&max)));
+        let response = core.handle_command(Command::Incr(key("key")));
+        assert_eq!(response, overflow_error);
+
+        let response = core.handle_command(Command::IncrBy(key("key"), string("1")));
+        assert_eq!(response, overflow_error);
+
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::BulkString(max.into_bytes()));
+
+        // Negating the decrement itself overflows
+        let min = isize::MIN.to_string();
+        let response = core.handle_command(Command::DecrBy(key("other"), string(&min)));
+        assert_eq!(response, overflow_error);
+    }
+
+    #[test]
+    fn getrange_setrange_strlen() {
+        let mut core = Core::new();
+
+        core.handle_command(Command::Set(key("key"), string("12345")));
+        let response = core.handle_command(Command::GetRange(key("key"), 0, 2));
+        assert_eq!(response, CommandResponse::BulkString(b"123".to_vec()));
+
+        // Integers read as their decimal representation
+        core.handle_command(Command::Incr(key("counter")));
+        let response = core.handle_command(Command::Strlen(key("counter")));
+        assert_eq!(response, CommandResponse::Integer(1));
+        let response = core.handle_command(Command::SetRange(key("counter"), 1, string("0")));
+        assert_eq!(response, CommandResponse::Integer(2));
+        let response = core.handle_command(Command::Get(key("counter")));
+        assert_eq!(response, CommandResponse::BorrowedBulkString(b"10"));
+
+        let response = core.handle_command(Command::SetRange(key("key"), -1, string("a")));
+        assert_eq!(
+            response,
+            CommandResponse::Error(String::from("ERR offset is out of range"))
+        );
+
+        let offset = STRING_MAX_SIZE as isize;
+        let response = core.handle_command(Command::SetRange(key("key"), offset, string("a")));
+        assert!(matches!(response, CommandResponse::Error(_)));
+    }
+
+    #[test]
+    fn setnx_and_set_nx_xx() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::SetNx(key("key"), string("1")));
+        assert_eq!(response, CommandResponse::Integer(1));
+        let response = core.handle_command(Command::SetNx(key("key"), string("2")));
+        assert_eq!(response, CommandResponse::Integer(0));
+
+        let set_nx = |value| Command::SetIf(key("other"), string(value), SetCondition::Nx);
+        let set_xx = |value| Command::SetIf(key("other"), string(value), SetCondition::Xx);
+
+        let response = core.handle_command(set_xx("1"));
+        assert_eq!(response, CommandResponse::Null);
+        assert_response_ok(core.handle_command(set_nx("2")));
+        let response = core.handle_command(set_nx("3"));
+        assert_eq!(response, CommandResponse::Null);
+        assert_response_ok(core.handle_command(set_xx("4")));
+
+        let response = core.handle_command(Command::Get(key("other")));
+        assert_eq!(response, CommandResponse::BorrowedBulkString(b"4"));
+
+        // A key of another type exists too
+        core.handle_command(Command::LPush(key("list"), vec![string("a")]));
+        let response = core.handle_command(Command::SetNx(key("list"), string("1")));
+        assert_eq!(response, CommandResponse::Integer(0));
+    }
+
+    #[test]
+    fn lpushx_rpushx() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::LPushX(key("list"), vec![string("a")]));
+        assert_eq!(response, CommandResponse::Integer(0));
+        let response = core.handle_command(Command::Exists(vec![key("list")]));
+        assert_eq!(response, CommandResponse::Integer(0));
+
+        core.handle_command(Command::RPush(key("list"), vec![string("b")]));
+        let response = core.handle_command(Command::LPushX(key("list"), vec![string("a")]));
+        assert_eq!(response, CommandResponse::Integer(2));
+        let response = core.handle_command(Command::RPushX(key("list"), vec![string("c")]));
+        assert_eq!(response, CommandResponse::Integer(3));
+
+        core.handle_command(Command::Set(key("key"), string("123")));
+        let response = core.handle_command(Command::RPushX(key("key"), vec![string("a")]));
+        assert_eq!(
+            response,
+            CommandResponse::Error(String::from(
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ))
+        );
+    }
+
+    #[test]
+    fn list_max_length() {
+        let mut core = Core::new();
+        core.set_list_max_length(Some(3));
+
+        for value in ["a", "b", "c", "d", "e"] {
+            let response = core.handle_command(Command::LPush(key("list"), vec![string(value)]));
+            assert!(matches!(response, CommandResponse::Integer(1..=3)));
+        }
+
+        let response = core.handle_command(Command::LPop(key("list"), 5));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"e".to_vec()),
+                CommandResponse::BulkString(b"d".to_vec()),
+                CommandResponse::BulkString(b"c".to_vec()),
+            ])
+        );
+
+        // LPUSHX and RPUSHX are capped the same way
+        core.handle_command(Command::RPush(key("list"), vec![string("a"); 3]));
+        let response = core.handle_command(Command::RPushX(key("list"), vec![string("b")]));
+        assert_eq!(response, CommandResponse::Integer(3));
+    }
+
+    #[test]
+    fn getdel_getset_on_list() {
+        let mut core = Core::new();
+        core.handle_command(Command::RPush(key("list"), vec![string("a"), string("b")]));
+        let wrong_type = CommandResponse::Error(String::from(
+            "WRONGTYPE Operation against a key holding the wrong kind of value",
+        ));
+
+        let response = core.handle_command(Command::GetDel(key("list")));
+        assert_eq!(response, wrong_type);
+        let response = core.handle_command(Command::Exists(vec![key("list")]));
+        assert_eq!(response, CommandResponse::Integer(1));
+
+        let response = core.handle_command(Command::GetSet(key("list"), string("value")));
+        assert_eq!(response, wrong_type);
+
+        // The list is left intact
+        let response = core.handle_command(Command::LPop(key("list"), 2));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"a".to_vec()),
+                CommandResponse::BulkString(b"b".to_vec()),
+            ])
+        );
+    }
+
+    #[test]
+    fn lmpop() {
+        let mut core = Core::new();
+        let keys = vec![key("empty"), key("list"), key("other")];
+        core.handle_command(Command::RPush(key("list"), vec![string("a"), string("b")]));
+        core.handle_command(Command::RPush(key("other"), vec![string("c")]));
+
+        // Pops from the first non-empty list only
+        let response = core.handle_command(Command::LMPop(keys.clone(), ListEnd::Back, Some(5)));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"list".to_vec()),
+                CommandResponse::Array(vec![
+                    CommandResponse::BulkString(b"b".to_vec()),
+                    CommandResponse::BulkString(b"a".to_vec()),
+                ]),
+            ])
+        );
+
+        let response = core.handle_command(Command::LMPop(keys, ListEnd::Front, None));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"other".to_vec()),
+                CommandResponse::Array(vec![CommandResponse::BulkString(b"c".to_vec())]),
+            ])
+        );
+
+        let keys = vec![key("empty"), key("list"), key("other")];
+        let response = core.handle_command(Command::LMPop(keys, ListEnd::Front, None));
+        assert_eq!(response, CommandResponse::NullArray);
+
+        core.handle_command(Command::Set(key("string"), string("value")));
+        let keys = vec![key("empty"), key("string")];
+        let response = core.handle_command(Command::LMPop(keys, ListEnd::Front, None));
+        assert_eq!(
+            response,
+            CommandResponse::Error(String::from(
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ))
+        );
+    }
+
+    #[test]
+    fn append_encoding_transition() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::Append(key("key"), string("hello")));
+        assert_eq!(response, CommandResponse::Integer(5));
+        let response = core.handle_command(Command::ObjectEncoding(key("key")));
+        assert_eq!(response, CommandResponse::BulkString(b"embstr".to_vec()));
+
+        let suffix = "a".repeat(40);
+        let response = core.handle_command(Command::Append(key("key"), string(&suffix)));
+        assert_eq!(response, CommandResponse::Integer(45));
+        let response = core.handle_command(Command::ObjectEncoding(key("key")));
+        assert_eq!(response, CommandResponse::BulkString(b"raw".to_vec()));
+
+        let response = core.handle_command(Command::ObjectEncoding(key("missing")));
+        assert_eq!(response, CommandResponse::Null);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::MemoryUsage(key("key"), 5));
+        assert_eq!(response, CommandResponse::Null);
+
+        core.handle_command(Command::Set(key("small"), string("a")));
+        core.handle_command(Command::Set(key("large"), string(&"a".repeat(1_000))));
+        let small = integer(core.handle_command(Command::MemoryUsage(key("small"), 5)));
+        let large = integer(core.handle_command(Command::MemoryUsage(key("large"), 5)));
+        assert!(small > 1);
+        assert!(large >= small + 999);
+    }
+
+    #[test]
+    fn memory_doctor_and_stats() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::MemoryDoctor);
+        assert!(matches!(response, CommandResponse::BulkString(report) if !report.is_empty()));
+
+        core.handle_command(Command::Set(key("key"), string("123")));
+        let response = core.handle_command(Command::MemoryStats);
+        if let CommandResponse::Array(items) = response {
+            assert_eq!(
+                items[0],
+                CommandResponse::BulkString(b"keys.count".to_vec())
+            );
+            assert_eq!(items[1], CommandResponse::Integer(1));
+            assert_eq!(
+                items[4],
+                CommandResponse::BulkString(b"dataset.bytes".to_vec())
+            );
+            assert!(matches!(items[5], CommandResponse::Integer(bytes) if bytes > 0));
+        } else {
+            panic!("Expect MEMORY STATS to return an array");
+        }
+    }
+
+    #[test]
+    fn info() {
+        let mut core = Core::new();
+        core.set_queue_depth(3);
+
+        if let CommandResponse::BulkString(info) = core.handle_command(Command::Info) {
+            let info = String::from_utf8(info).unwrap();
+            assert!(info.contains("uptime_in_seconds:0\r\n"));
+            assert!(info.contains("uptime_in_days:0\r\n"));
+            assert!(info.contains("blocked_clients:0\r\n"));
+            assert!(info.contains("queue_depth:3\r\n"));
+            assert!(!info.contains("db0:"));
+        } else {
+            panic!("Expect INFO to return a bulk string");
+        }
+    }
+
+    #[test]
+    fn info_keyspace() {
+        let mut core = Core::new();
+        for name in ["a", "b", "c"] {
+            core.handle_command(Command::Set(key(name), string("1")));
+        }
+        core.handle_command(Command::Expire(key("a"), 100));
+
+        match core.handle_command(Command::Info) {
+            CommandResponse::BulkString(info) => {
+                let info = String::from_utf8(info).unwrap();
+                assert!(info.contains("# Keyspace\r\ndb0:keys=3,expires=1,avg_ttl=0\r\n"));
+            }
+            response => panic!("Unexpected response {response:?}"),
+        }
+    }
+
+    #[test]
+    fn info_memory() {
+        let mut core = Core::new();
+        let used_memory = |core: &mut Core| match core.handle_command(Command::Info) {
+            CommandResponse::BulkString(info) => {
+                let info = String::from_utf8(info).unwrap();
+                assert!(info.contains("mem_fragmentation_ratio:1.00\r\n"));
+                assert!(info.contains("maxmemory:0\r\n"));
+                info.lines()
+                    .find_map(|line| line.strip_prefix("used_memory:"))
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap()
+            }
+            response => panic!("Unexpected response {response:?}"),
+        };
+
+        let empty = used_memory(&mut core);
+        assert!(empty > 0);
+        core.handle_command(Command::Set(key("key"), string(&"a".repeat(1_000))));
+        assert!(used_memory(&mut core) >= empty + 1_000);
+
+        assert_eq!(Core::human_bytes(512), "512B");
+        assert_eq!(Core::human_bytes(1536), "1.50K");
+        assert_eq!(Core::human_bytes(3 * 1024 * 1024), "3.00M");
+    }
+
+    #[test]
+    fn command_list_and_count() {
+        let mut core = Core::new();
+
+        let names = match core.handle_command(Command::CommandList) {
+            CommandResponse::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    CommandResponse::BulkString(name) => String::from_utf8(name).unwrap(),
+                    _ => panic!("Expect command names as bulk strings"),
+                })
+                .collect::<Vec<String>>(),
+            _ => panic!("Expect COMMAND LIST to return an array"),
+        };
+
+        for name in ["get", "set", "del"] {
+            assert!(names.iter().any(|item| item == name));
+        }
+
+        let count = integer(core.handle_command(Command::CommandCount));
+        assert_eq!(names.len() as isize, count);
+    }
+
+    #[test]
+    fn command_docs() {
+        let mut core = Core::new();
+
+        let command = Command::CommandDocs(vec![string("GET"), string("unknown")]);
+        let response = core.handle_command(command);
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"get".to_vec()),
+                CommandResponse::Array(vec![
+                    CommandResponse::BulkString(b"summary".to_vec()),
+                    CommandResponse::BulkString(b"Returns the string value of a key.".to_vec()),
+                    CommandResponse::BulkString(b"arity".to_vec()),
+                    CommandResponse::Integer(2),
+                    CommandResponse::BulkString(b"arguments".to_vec()),
+                    CommandResponse::Array(vec![CommandResponse::Array(vec![
+                        CommandResponse::BulkString(b"name".to_vec()),
+                        CommandResponse::BulkString(b"key".to_vec()),
+                        CommandResponse::BulkString(b"type".to_vec()),
+                        CommandResponse::BulkString(b"key".to_vec()),
+                    ])]),
+                ]),
+            ])
+        );
+
+        // Name and docs of every command
+        let count = integer(core.handle_command(Command::CommandCount));
+        if let CommandResponse::Array(items) = core.handle_command(Command::CommandDocs(vec![])) {
+            assert_eq!(items.len() as isize, count * 2);
+        } else {
+            panic!("Expect COMMAND DOCS to return an array");
+        }
+    }
+
+    #[test]
+    fn command_info() {
+        let mut core = Core::new();
+
+        let command = Command::CommandInfo(vec![string("GET"), string("unknown")]);
+        let response = core.handle_command(command);
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::Array(vec![
+                    CommandResponse::BulkString(b"get".to_vec()),
+                    CommandResponse::Integer(2),
+                    CommandResponse::Array(vec![
+                        CommandResponse::SimpleString(b"readonly"),
+                        CommandResponse::SimpleString(b"fast"),
+                    ]),
+                    CommandResponse::Integer(1),
+                    CommandResponse::Integer(1),
+                    CommandResponse::Integer(1),
+                ]),
+                CommandResponse::NullArray,
+            ])
+        );
+    }
+
+    #[test]
+    fn debug() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::Debug(DebugSubcommand::NoOp));
+        assert_response_ok(response);
+
+        let response = core.handle_command(Command::Debug(DebugSubcommand::Reload));
+        assert!(matches!(response, CommandResponse::Error(_)));
+    }
+
+    #[test]
+    fn debug_expire() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("key"), string("123")));
+        core.handle_command(Command::Expire(key("key"), 100));
+
+        let response = core.handle_command(Command::Debug(DebugSubcommand::Expire(key("key"))));
+        assert_response_ok(response);
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::Null);
+
+        let response = core.handle_command(Command::Debug(DebugSubcommand::Expire(key("key"))));
+        assert_eq!(response, CommandError::NoSuchKey.to_response());
+    }
+
+    #[test]
+    fn list_encoding_threshold() {
+        let mut core = Core::new();
+        let encoding =
+            |core: &mut Core| match core.handle_command(Command::ObjectEncoding(key("list"))) {
+                CommandResponse::BulkString(encoding) => String::from_utf8(encoding).unwrap(),
+                response => panic!("Unexpected response {response:?}"),
+            };
+
+        core.handle_command(Command::RPush(key("list"), vec![string("a"); 128]));
+        assert_eq!(encoding(&mut core), "listpack");
+        core.handle_command(Command::RPush(key("list"), vec![string("a")]));
+        assert_eq!(encoding(&mut core), "quicklist");
+
+        let response = core.handle_command(Command::ConfigSet(vec![(
+            string("LIST-MAX-LISTPACK-SIZE"),
+            string("200"),
+        )]));
+        assert_response_ok(response);
+        assert_eq!(encoding(&mut core), "listpack");
+
+        let response = core.handle_command(Command::ConfigGet(vec![string("list-*")]));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"list-max-listpack-size".to_vec()),
+                CommandResponse::BulkString(b"200".to_vec()),
+            ])
+        );
+        let response = core.handle_command(Command::ConfigGet(vec![string("maxmemory")]));
+        assert_eq!(response, CommandResponse::Array(vec![]));
+    }
+
+    #[test]
+    fn config_set_errors() {
+        let mut core = Core::new();
+
+        let response = core.handle_command(Command::ConfigSet(vec![(
+            string("list-max-listpack-size"),
+            string("-2"),
+        )]));
+        assert_eq!(
+            response,
+            CommandResponse::Error(String::from(
+                "ERR CONFIG SET failed (possibly related to argument 'list-max-listpack-size') - argument must be a positive integer"
+            ))
+        );
+
+        // Nothing is set when one of the parameters is unknown
+        let response = core.handle_command(Command::ConfigSet(vec![
+            (string("list-max-listpack-size"), string("10")),
+            (string("unknown"), string("1")),
+        ]));
+        assert_eq!(
+            response,
+            CommandResponse::Error(String::from(
+                "ERR Unknown option or number of arguments for CONFIG SET - 'unknown'"
+            ))
+        );
+        let response = core.handle_command(Command::ConfigGet(vec![string("*")]));
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"list-max-listpack-size".to_vec()),
+                CommandResponse::BulkString(b"128".to_vec()),
+            ])
+        );
+    }
+
+    #[test]
+    fn debug_object() {
+        let mut core = Core::new();
+        core.handle_command(Command::Set(key("number"), string("12345")));
+        core.handle_command(Command::Set(key("string"), string("value")));
+
+        let command = Command::Debug(DebugSubcommand::Object(key("number")));
+        if let CommandResponse::BulkString(details) = core.handle_command(command) {
+            let details = String::from_utf8(details).unwrap();
+            assert!(details.starts_with("Value at:"));
+            assert!(details.contains(" encoding:int serializedlength:3"));
+        } else {
+            panic!("Expect DEBUG OBJECT to return a bulk string");
+        }
+
+        let command = Command::Debug(DebugSubcommand::Object(key("string")));
+        if let CommandResponse::BulkString(details) = core.handle_command(command) {
+            let details = String::from_utf8(details).unwrap();
+            assert!(details.contains(" encoding:embstr serializedlength:6"));
+        } else {
+            panic!("Expect DEBUG OBJECT to return a bulk string");
+        }
+
+        let command = Command::Debug(DebugSubcommand::Object(key("missing")));
+        assert_eq!(
+            core.handle_command(command),
+            CommandResponse::Error(String::from("ERR no such key"))
+        );
+    }
+
+    #[test]
+    fn debug_keyversion() {
+        let mut core = Core::new();
+        let version = Command::Debug(DebugSubcommand::KeyVersion(key("key")));
+
+        let response = core.handle_command(version.clone());
+        assert_eq!(response, CommandResponse::Integer(0));
+
+        core.handle_command(Command::Set(key("key"), string("1")));
+        core.handle_command(Command::Incr(key("key")));
+        let response = core.handle_command(version.clone());
+        assert_eq!(response, CommandResponse::Integer(2));
+
+        core.handle_command(Command::Get(key("key")));
+        let response = core.handle_command(version);
+        assert_eq!(response, CommandResponse::Integer(2));
+    }
+
+    #[test]
+    fn debug_set_active_expire() {
+        let mut core = Core::new();
+        let disable = Command::Debug(DebugSubcommand::SetActiveExpire(false));
+        assert_response_ok(core.handle_command(disable));
+
+        core.handle_command(Command::Set(key("key"), string("123")));
+        core.handle_command(Command::Expire(key("key"), 1));
+        thread::sleep(Duration::from_millis(1_100));
+
+        // The sweep is a no-op, the expired key is still counted but can't be read
+        core.handle_command(Command::ExpIntervalCheck);
+        let response = core.handle_command(Command::DbSize);
+        assert_eq!(response, CommandResponse::Integer(1));
+        let response = core.handle_command(Command::Get(key("key")));
+        assert_eq!(response, CommandResponse::Null);
+
+        let enable = Command::Debug(DebugSubcommand::SetActiveExpire(true));
+        assert_response_ok(core.handle_command(enable));
+
+        core.handle_command(Command::ExpIntervalCheck);
+        let response = core.handle_command(Command::DbSize);
+        assert_eq!(response, CommandResponse::Integer(0));
+    }
+
+    #[test]
+    fn client_modes() {
+        let mut core = Core::new();
+        core.execute_str("SET key value");
+
+        assert_response_ok(core.execute_str("CLIENT NO-TOUCH ON"));
+        assert_response_ok(core.execute_str("CLIENT NO-EVICT ON"));
+
+        // Reads are not affected, there are no access stats to skip
+        let response = core.execute_str("GET key");
+        assert_eq!(response, CommandResponse::BorrowedBulkString(b"value"));
+        assert_response_ok(core.execute_str("CLIENT NO-TOUCH OFF"));
+    }
+
+    #[test]
+    fn execute_str() {
+        let mut core = Core::new();
+
+        assert_response_ok(core.execute_str("SET key \"hello world\""));
+        let response = core.execute_str("GET key");
+        assert_eq!(
+            response,
+            CommandResponse::BorrowedBulkString(b"hello world")
+        );
+
+        // Quotes can be escaped inside double quotes, single quotes are taken literally
+        assert_response_ok(core.execute_str(r#"SET key "say \"hi\"""#));
+        let response = core.execute_str("GET key");
+        assert_eq!(
+            response,
+            CommandResponse::BorrowedBulkString(br#"say "hi""#)
+        );
+        assert_response_ok(core.execute_str(r#"SET key 'a "b"  c'"#));
+        let response = core.execute_str("GET key");
+        assert_eq!(
+            response,
+            CommandResponse::BorrowedBulkString(br#"a "b"  c"#)
+        );
+
+        let response = core.execute_str("  RPUSH   list 'first item' \"\" third  ");
+        assert_eq!(response, CommandResponse::Integer(3));
+        let response = core.execute_str("LPOP list 3");
+        assert_eq!(
+            response,
+            CommandResponse::Array(vec![
+                CommandResponse::BulkString(b"first item".to_vec()),
+                CommandResponse::BulkString(b"".to_vec()),
+                CommandResponse::BulkString(b"third".to_vec()),
+            ])
+        );
+    }
+
+    #[test]
+    fn dispatch_routes_every_command() {
+        let mut core = Core::new();
+        let ok = || CommandResponse::SimpleString(b"OK");
+        let bulk = |value: &str| CommandResponse::BulkString(string(value));
+        // 2100-01-01T00:00:00Z
+        let timestamp = 4_102_444_800;
+
+        let cases = vec![
+            (Command::Set(key("string"), string("1")), ok()),
+            (
+                Command::SetNx(key("string"), string("2")),
+                CommandResponse::Integer(0),
+            ),
+            (
+                Command::SetIf(key("string"), string("3"), SetCondition::Xx),
+                ok(),
+            ),
+            (Command::GetSet(key("string"), string("1")), bulk("3")),
+            (
+                Command::Get(key("string")),
+                CommandResponse::BorrowedBulkString(b"1"),
+            ),
+            (
+                Command::Append(key("string"), string("0")),
+                CommandResponse::Integer(2),
+            ),
+            (Command::Strlen(key("string")), CommandResponse::Integer(2)),
+            (Command::GetRange(key("string"), 0, 0), bulk("1")),
+            (
+                Command::SetRange(key("string"), 1, string("1")),
+                CommandResponse::Integer(2),
+            ),
+            (Command::Incr(key("string")), CommandResponse::Integer(12)),
+            (Command::Decr(key("string")), CommandResponse::Integer(11)),
+            (
+                Command::IncrBy(key("string"), string("10")),
+                CommandResponse::Integer(21),
+            ),
+            (
+                Command::DecrBy(key("string"), string("1")),
+                CommandResponse::Integer(20),
+            ),
+            (Command::MSet(vec![key("other")], vec![string("x")]), ok()),
+            (
+                Command::MGet(vec![key("other"), key("missing")]),
+                CommandResponse::Array(vec![
+                    CommandResponse::BorrowedBulkString(b"x"),
+                    CommandResponse::Null,
+                ]),
+            ),
+            (Command::GetDel(key("other")), bulk("x")),
+            (
+                Command::RPush(key("list"), vec![string("a"), string("b")]),
+                CommandResponse::Integer(2),
+            ),
+            (
+                Command::LPush(key("list"), vec![string("c")]),
+                CommandResponse::Integer(3),
+            ),
+            (
+                Command::RPushX(key("list"), vec![string("d")]),
+                CommandResponse::Integer(4),
+            ),
+            (
+                Command::LPushX(key("missing"), vec![string("e")]),
+                CommandResponse::Integer(0),
+            ),
+            (
+                Command::LPop(key("list"), 1),
+                CommandResponse::Array(vec![bulk("c")]),
+            ),
+            (
+                Command::RPop(key("list"), 1),
+                CommandResponse::Array(vec![bulk("d")]),
+            ),
+            (
+                Command::LMPop(vec![key("missing"), key("list")], ListEnd::Front, None),
+                CommandResponse::Array(vec![bulk("list"), CommandResponse::Array(vec![bulk("a")])]),
+            ),
+            (
+                Command::Exists(vec![key("string"), key("list")]),
+                CommandResponse::Integer(2),
+            ),
+            (
+                Command::Expire(key("missing"), 10),
+                CommandResponse::Integer(0),
+            ),
+            (
+                Command::PExpire(key("missing"), 10),
+                CommandResponse::Integer(0),
+            ),
+            (
+                Command::ExpireAt(key("list"), timestamp),
+                CommandResponse::Integer(1),
+            ),
+            (
+                Command::ExpireTime(key("list")),
+                CommandResponse::Integer(timestamp),
+            ),
+            (
+                Command::PExpireAt(key("string"), timestamp * 1000),
+                CommandResponse::Integer(1),
+            ),
+            (
+                Command::PExpireTime(key("string")),
+                CommandResponse::Integer(timestamp * 1000),
+            ),
+            (Command::Ttl(key("missing")), CommandResponse::Integer(-2)),
+            (Command::PTtl(key("missing")), CommandResponse::Integer(-2)),
+            (Command::DbSize, CommandResponse::Integer(2)),
+            (Command::Del(vec![key("list")]), CommandResponse::Integer(1)),
+            (Command::Flush, ok()),
+            (Command::Quit, ok()),
+            (
+                Command::Role,
+                CommandResponse::Array(vec![
+                    bulk("master"),
+                    CommandResponse::Integer(0),
+                    CommandResponse::Array(vec![]),
+                ]),
+            ),
+            (
+                Command::ReplicaOf,
+                CommandError::ReplicationDisabled.to_response(),
+            ),
+            (
+                Command::Failover,
+                CommandError::ReplicationDisabled.to_response(),
+            ),
+            (Command::Monitor, ok()),
+            (
+                Command::CommandCount,
+                CommandResponse::Integer(command_table::COMMANDS.len() as isize),
+            ),
+            (
+                Command::ConfigSet(vec![(string("list-max-listpack-size"), string("64"))]),
+                ok(),
+            ),
+            (
+                Command::ConfigGet(vec![string("list-max-listpack-size")]),
+                CommandResponse::Array(vec![bulk("list-max-listpack-size"), bulk("64")]),
+            ),
+            (Command::ClientNoEvict(true), ok()),
+            (Command::ClientNoTouch(false), ok()),
+            (
+                Command::ObjectEncoding(key("missing")),
+                CommandResponse::Null,
+            ),
+            (
+                Command::MemoryUsage(key("missing"), 5),
+                CommandResponse::Null,
+            ),
+            (Command::Debug(DebugSubcommand::SetActiveExpire(true)), ok()),
+            (
+                Command::Debug(DebugSubcommand::KeyVersion(key("missing"))),
+                CommandResponse::Integer(0),
+            ),
+            (
+                Command::Debug(DebugSubcommand::Object(key("missing"))),
+                CommandError::NoSuchKey.to_response(),
+            ),
+            (
+                Command::Debug(DebugSubcommand::Expire(key("missing"))),
+                CommandError::NoSuchKey.to_response(),
+            ),
+            (
+                Command::Debug(DebugSubcommand::Reload),
+                CommandError::PersistenceUnsupported.to_response(),
+            ),
+            (Command::Debug(DebugSubcommand::Sleep(Duration::ZERO)), ok()),
+            (Command::Debug(DebugSubcommand::NoOp), ok()),
+        ];
+
+        for (command, expected) in cases {
+            let name = command.name().unwrap();
+            assert!(command_table::find(name.as_bytes()).is_some(), "{name}");
+            assert_eq!(core.handle_command(command), expected, "{name}");
+        }
+
+        // Replies which vary are compared with what their handler replies
+        let families = [
+            HelpFamily::Command,
+            HelpFamily::Object,
+            HelpFamily::Debug,
+            HelpFamily::Memory,
+            HelpFamily::Config,
+            HelpFamily::Client,
+        ];
+        let mut cases = families
+            .into_iter()
+            .map(|family| (Command::Help(family.clone()), Core::help(family)))
+            .collect::<Vec<_>>();
+        cases.extend([
+            (Command::CommandList, Core::command_list()),
+            (Command::CommandInfo(vec![]), Core::command_info(&[])),
+            (
+                Command::CommandDocs(vec![string("get")]),
+                Core::command_docs(&[string("get")]),
+            ),
+            (Command::MemoryDoctor, core.memory_doctor()),
+            (Command::MemoryStats, core.memory_stats()),
+            (Command::Info, core.info()),
+        ]);
+
+        for (command, expected) in cases {
+            let name = command.name().unwrap();
+            assert!(command_table::find(name.as_bytes()).is_some(), "{name}");
+            assert_eq!(core.handle_command(command), expected, "{name}");
+        }
+
+        assert_eq!(Command::Time.name(), Some("time"));
+        assert_eq!(Command::ExpIntervalCheck.name(), None);
+    }
+
+    #[test]
+    fn command_error_messages() {
+        let cases = [
+            (
+                CommandError::WrongType,
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+            (
+                CommandError::NotInteger,
+                "ERR value is not an integer or out of range",
+            ),
+            (
+                CommandError::Overflow,
+                "ERR increment or decrement would overflow",
+            ),
+            (CommandError::NoSuchKey, "ERR no such key"),
+            (CommandError::SyntaxError, "ERR syntax error"),
+            (CommandError::OutOfRange, "ERR offset is out of range"),
+            (
+                CommandError::StringTooLong,
+                "ERR string exceeds maximum allowed size (proto-max-bulk-len)",
+            ),
+            (
+                CommandError::UnknownConfigParameter(String::from("maxmemory")),
+                "ERR Unknown option or number of arguments for CONFIG SET - 'maxmemory'",
+            ),
+            (
+                CommandError::InvalidConfigValue(String::from("list-max-listpack-size")),
+                "ERR CONFIG SET failed (possibly related to argument 'list-max-listpack-size') - argument must be a positive integer",
+            ),
+            (
+                CommandError::PersistenceUnsupported,
+                "ERR DEBUG RELOAD requires persistence, which is not supported",
+            ),
+            (
+                CommandError::ReplicationDisabled,
+                "ERR This instance has replication disabled",
+            ),
+        ];
+
+        for (error, message) in cases {
+            assert_eq!(
+                error.to_response(),
+                CommandResponse::Error(String::from(message))
+            );
+        }
+
+        assert_eq!(
+            CommandError::from(StorageError::WrongOperationType),
+            CommandError::WrongType
+        );
+    }
+
+    fn assert_response_ok(response: CommandResponse) {
+        let ok_response = CommandResponse::SimpleString(b"OK");
+        assert_eq!(response, ok_response);
+    }
+
+    fn integer(response: CommandResponse) -> isize {
+        match response {
+            CommandResponse::Integer(integer) => integer,
+            _ => panic!("Expect an integer response, got {response:?}"),
+        }
     }
 
     fn key(key: &str) -> Key {
@@ -345,4 +2393,56 @@ mod tests {
     fn string(value: &str) -> Vec<u8> {
         value.as_bytes().to_vec()
     }
+
+    impl Core {
+        /// Run a command written the way redis-cli takes it, e.g. `SET key "a value"`.
+        /// It goes through the same parser as the commands of a connection
+        fn execute_str(&mut self, command: &str) -> CommandResponse<'_> {
+            let arguments = split_arguments(command);
+            let mut input = format!("*{}\r\n", arguments.len()).into_bytes();
+            for argument in arguments {
+                input.extend_from_slice(format!("${}\r\n", argument.len()).as_bytes());
+                input.extend_from_slice(argument.as_bytes());
+                input.extend_from_slice(b"\r\n");
+            }
+
+            let command = match parse_commands(&input).pop() {
+                Some(Ok(command)) => command,
+                result => panic!("Invalid command {command:?}: {result:?}"),
+            };
+            self.handle_command(command)
+        }
+    }
+
+    // Arguments are separated by spaces, unless quoted with double or single
+    // quotes. Inside double quotes, a backslash escapes the next character
+    fn split_arguments(command: &str) -> Vec<String> {
+        let mut arguments = vec![];
+        let mut chars = command.chars().peekable();
+
+        loop {
+            while chars.next_if(|char| char.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                return arguments;
+            }
+
+            let mut argument = String::new();
+            while let Some(char) = chars.next_if(|char| !char.is_whitespace()) {
+                match char {
+                    '"' => {
+                        while let Some(char) = chars.next() {
+                            match char {
+                                '"' => break,
+                                '\\' => argument.extend(chars.next()),
+                                char => argument.push(char),
+                            }
+                        }
+                    }
+                    '\'' => argument.extend(chars.by_ref().take_while(|char| *char != '\'')),
+                    char => argument.push(char),
+                }
+            }
+            arguments.push(argument);
+        }
+    }
 }
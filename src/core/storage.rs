@@ -1,9 +1,13 @@
 /// This module handles all the in-memory operations related to
 /// storing/retrieving data
 use super::Key;
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
 use std::collections::{BinaryHeap, HashMap, VecDeque};
-use std::time::{Duration, Instant};
+use std::hash::{BuildHasher, Hasher};
+use std::mem::size_of;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, PartialEq)]
 pub enum StorageValue {
@@ -12,7 +16,164 @@ pub enum StorageValue {
     List(VecDeque<Vec<u8>>),
 }
 
-struct ValueWithExpiration(StorageValue, Option<Instant>);
+// Strings up to this length are allocated together with their object in Redis
+const EMBSTR_MAX_LENGTH: usize = 44;
+// Lists with more elements are stored as a quicklist, see list-max-listpack-size
+pub const DEFAULT_LIST_MAX_LISTPACK_SIZE: usize = 128;
+
+impl StorageValue {
+    /// The encoding Redis would use to store this value. It's derived from
+    /// the current value, so it follows the value as it grows or shrinks
+    pub fn encoding(&self, list_max_listpack_size: usize) -> &'static str {
+        match self {
+            StorageValue::Integer(_) => "int",
+            StorageValue::String(bytes) if is_integer(bytes) => "int",
+            StorageValue::String(bytes) if bytes.len() <= EMBSTR_MAX_LENGTH => "embstr",
+            StorageValue::String(_) => "raw",
+            StorageValue::List(list) if list.len() <= list_max_listpack_size => "listpack",
+            StorageValue::List(_) => "quicklist",
+        }
+    }
+
+    /// The value as seen by string commands. Integers are an internal encoding,
+    /// they read as their decimal representation
+    pub fn as_string_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        match self {
+            StorageValue::String(bytes) => Some(Cow::Borrowed(bytes)),
+            StorageValue::Integer(integer) => Some(Cow::Owned(integer.to_string().into_bytes())),
+            StorageValue::List(_) => None,
+        }
+    }
+
+    /// Same as as_string_bytes, but for modifications. An integer is converted
+    /// into a string first, as it's unlikely to stay an integer afterward
+    fn as_string_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+        if let StorageValue::Integer(integer) = self {
+            *self = StorageValue::String(integer.to_string().into_bytes());
+        }
+
+        match self {
+            StorageValue::String(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Number of bytes the value takes in an RDB file, without compression
+    fn serialized_length(&self) -> usize {
+        match self {
+            StorageValue::Integer(integer) => serialized_integer_length(*integer),
+            StorageValue::String(bytes) => match parse_integer(bytes) {
+                Some(integer) => serialized_integer_length(integer),
+                None => serialized_string_length(bytes),
+            },
+            StorageValue::List(list) => {
+                let items_length: usize =
+                    list.iter().map(|item| serialized_string_length(item)).sum();
+                serialized_length_prefix(list.len()) + items_length
+            }
+        }
+    }
+
+    /// Number of bytes the value occupies outside of its enum, e.g. the
+    /// content of a string or the items of a list
+    fn heap_size(&self) -> usize {
+        match self {
+            StorageValue::String(bytes) => bytes.capacity(),
+            StorageValue::Integer(_) => 0,
+            StorageValue::List(list) => {
+                let items_size: usize = list.iter().map(|item| item.capacity()).sum();
+                list.capacity() * size_of::<Vec<u8>>() + items_size
+            }
+        }
+    }
+
+    /// Same as heap_size, except the items of a list are estimated from the first
+    /// samples of them, so a large list isn't walked. 0 samples means every item
+    fn sampled_heap_size(&self, samples: usize) -> usize {
+        match self {
+            StorageValue::List(list) if samples > 0 && samples < list.len() => {
+                let sampled_size: usize =
+                    list.iter().take(samples).map(|item| item.capacity()).sum();
+                list.capacity() * size_of::<Vec<u8>>() + sampled_size * list.len() / samples
+            }
+            value => value.heap_size(),
+        }
+    }
+}
+
+// Small integers are saved in binary, the others as strings
+fn serialized_integer_length(integer: isize) -> usize {
+    if i8::try_from(integer).is_ok() {
+        2
+    } else if i16::try_from(integer).is_ok() {
+        3
+    } else if i32::try_from(integer).is_ok() {
+        5
+    } else {
+        serialized_string_length(integer.to_string().as_bytes())
+    }
+}
+
+fn serialized_string_length(bytes: &[u8]) -> usize {
+    serialized_length_prefix(bytes.len()) + bytes.len()
+}
+
+fn serialized_length_prefix(length: usize) -> usize {
+    if length < 1 << 6 {
+        1
+    } else if length < 1 << 14 {
+        2
+    } else if length <= u32::MAX as usize {
+        5
+    } else {
+        9
+    }
+}
+
+// Whether the bytes are the canonical representation of an integer, e.g. "12" but not "012"
+fn is_integer(bytes: &[u8]) -> bool {
+    parse_integer(bytes).is_some()
+}
+
+fn parse_integer(bytes: &[u8]) -> Option<isize> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|string| string.parse::<isize>().ok())
+        .filter(|integer| integer.to_string().as_bytes() == bytes)
+}
+
+// The last field is the version of the key, see Storage::version
+struct ValueWithExpiration(StorageValue, Option<Expiration>, u64);
+
+impl ValueWithExpiration {
+    fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.1, Some(exp) if exp.instant <= now)
+    }
+}
+
+// The instant drives the expiration, as it's not affected by clock changes.
+// The Unix time is kept for EXPIRETIME, which has to report what was set
+#[derive(Clone, Copy)]
+struct Expiration {
+    instant: Instant,
+    unix_time_ms: u64,
+}
+
+fn now_unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is set before Unix epoch")
+        .as_millis() as u64
+}
+
+// Every RandomState is seeded differently, which is random enough for sampling
+fn random_number() -> usize {
+    RandomState::new().build_hasher().finish() as usize
+}
+
+fn entry_size(key: &[u8], value: &ValueWithExpiration, samples: usize) -> usize {
+    key.len() + size_of::<ValueWithExpiration>() + value.0.sampled_heap_size(samples)
+}
 
 struct KeyWithExpiration(Key, Instant);
 
@@ -46,12 +207,19 @@ impl Ord for KeyWithExpiration {
 pub struct Storage {
     hash_map: HashMap<Vec<u8>, ValueWithExpiration>,
     key_expiration_queue: BinaryHeap<KeyWithExpiration>,
+    // The last version given to a modified key
+    last_version: u64,
+    list_max_listpack_size: usize,
+    // Time of the last tick, used instead of reading the clock when the
+    // coarse clock is enabled. See set_coarse_clock
+    coarse_now: Option<Instant>,
 }
 
 #[derive(Debug)]
 pub enum StorageError {
     WrongOperationType,
     NotInteger,
+    Overflow,
 }
 
 pub trait ToStorageValue {
@@ -107,6 +275,7 @@ impl ToStorageValue for Vec<String> {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum ListEnd {
     Front,
     Back,
@@ -117,52 +286,300 @@ impl Storage {
         Self {
             hash_map: HashMap::new(),
             key_expiration_queue: BinaryHeap::new(),
+            last_version: 0,
+            list_max_listpack_size: DEFAULT_LIST_MAX_LISTPACK_SIZE,
+            coarse_now: None,
+        }
+    }
+
+    /// With the coarse clock, reads check expirations against the time of the
+    /// last tick instead of reading the clock every time. Keys may then outlive
+    /// their expiration by up to the interval between ticks
+    pub fn set_coarse_clock(&mut self, enabled: bool) {
+        self.coarse_now = enabled.then(Instant::now);
+    }
+
+    /// Refresh the coarse clock, if it's enabled
+    pub fn tick(&mut self) {
+        if self.coarse_now.is_some() {
+            self.coarse_now = Some(Instant::now());
+        }
+    }
+
+    // The time reads check expirations against
+    fn now(&self) -> Instant {
+        self.coarse_now.unwrap_or_else(Instant::now)
+    }
+
+    /// Remove every key. Versions keep counting from where they were, so a key
+    /// created again doesn't get a version it had before the flush
+    pub fn flush(&mut self) {
+        self.hash_map.clear();
+        self.key_expiration_queue.clear();
+    }
+
+    /// Changes every time the key is modified, which is what WATCH builds on.
+    /// A missing key has version 0, so deleting a key changes its version too
+    pub fn version(&self, key: &Key) -> u64 {
+        let now = self.now();
+        match self.hash_map.get(&key.0) {
+            Some(value) if value.is_expired(now) => 0,
+            Some(ValueWithExpiration(_, _, version)) => *version,
+            None => 0,
         }
     }
 
+    // Give the key a version greater than any given so far
+    fn touch(&mut self, key: &Key) {
+        if let Some(value) = self.hash_map.get_mut(&key.0) {
+            self.last_version += 1;
+            value.2 = self.last_version;
+        }
+    }
+
+    /// The value of a string key. Every other type is listed explicitly, so a
+    /// new type can't be read as a string by mistake
     pub fn get(&self, key: &Key) -> Result<Option<&StorageValue>, StorageError> {
         match self.get_raw(key) {
             None => Ok(None),
             value @ Some(StorageValue::String(_)) => Ok(value),
             value @ Some(StorageValue::Integer(_)) => Ok(value),
-            _ => Err(StorageError::WrongOperationType),
+            Some(StorageValue::List(_)) => Err(StorageError::WrongOperationType),
         }
     }
 
     pub fn set<T: ToStorageValue>(&mut self, key: Key, value: T) {
-        self.hash_map
-            .insert(key.0, ValueWithExpiration(value.to_storage_value(), None));
+        self.last_version += 1;
+        self.hash_map.insert(
+            key.0,
+            ValueWithExpiration(value.to_storage_value(), None, self.last_version),
+        );
+    }
+
+    /// Replace the value of a string key in one go, returning the previous value.
+    /// A key holding another type is left untouched
+    pub fn get_and_set<T: ToStorageValue>(
+        &mut self,
+        key: Key,
+        value: T,
+    ) -> Result<Option<StorageValue>, StorageError> {
+        self.get(&key)?;
+
+        let now = self.now();
+        self.last_version += 1;
+        let previous = self.hash_map.insert(
+            key.0,
+            ValueWithExpiration(value.to_storage_value(), None, self.last_version),
+        );
+        Ok(previous
+            .filter(|previous| !previous.is_expired(now))
+            .map(|ValueWithExpiration(value, _, _)| value))
+    }
+
+    /// Delete a string key, returning its value. A key holding another type is left untouched
+    pub fn get_and_delete(&mut self, key: &Key) -> Result<Option<StorageValue>, StorageError> {
+        self.get(key)?;
+
+        let now = self.now();
+        Ok(self
+            .hash_map
+            .remove(&key.0)
+            .filter(|previous| !previous.is_expired(now))
+            .map(|ValueWithExpiration(value, _, _)| value))
     }
 
     // Can use negative value as decr
     pub fn incr(&mut self, key: &Key, inc: isize) -> Result<isize, StorageError> {
         match self.get_raw_mut(key) {
             None => {
-                self.set(key.clone(), 1 as isize);
-                Ok(1)
+                self.set(key.clone(), inc);
+                Ok(inc)
+            }
+            Some(value) => {
+                let integer = match value {
+                    StorageValue::Integer(integer) => *integer,
+                    // E.g. a number set with SET
+                    StorageValue::String(bytes) => {
+                        parse_integer(bytes).ok_or(StorageError::NotInteger)?
+                    }
+                    StorageValue::List(_) => return Err(StorageError::WrongOperationType),
+                };
+
+                let integer = integer.checked_add(inc).ok_or(StorageError::Overflow)?;
+                *value = StorageValue::Integer(integer);
+                self.touch(key);
+                Ok(integer)
+            }
+        }
+    }
+
+    // Append to the string in place, so the key keeps its expiration
+    pub fn append(&mut self, key: Key, mut bytes: Vec<u8>) -> Result<usize, StorageError> {
+        let value = match self.get_raw_mut(&key) {
+            Some(value) => value,
+            None => {
+                let len = bytes.len();
+                self.set(key, bytes);
+                return Ok(len);
             }
-            Some(StorageValue::Integer(integer)) => {
-                *integer += inc;
-                Ok(*integer)
+        };
+
+        let len = match value.as_string_bytes_mut() {
+            Some(string) => {
+                string.append(&mut bytes);
+                string.len()
             }
-            _ => Err(StorageError::NotInteger),
+            None => return Err(StorageError::WrongOperationType),
+        };
+
+        self.touch(&key);
+        Ok(len)
+    }
+
+    pub fn strlen(&self, key: &Key) -> Result<usize, StorageError> {
+        match self.get_raw(key).map(|value| value.as_string_bytes()) {
+            None => Ok(0),
+            Some(Some(string)) => Ok(string.len()),
+            Some(None) => Err(StorageError::WrongOperationType),
         }
     }
 
+    /// Substring between start and end, both inclusive. Negative offsets count
+    /// from the end of the string and out of range offsets are clamped
+    pub fn getrange(&self, key: &Key, start: isize, end: isize) -> Result<Vec<u8>, StorageError> {
+        let string = match self.get_raw(key).map(|value| value.as_string_bytes()) {
+            None => return Ok(vec![]),
+            Some(Some(string)) => string,
+            Some(None) => return Err(StorageError::WrongOperationType),
+        };
+
+        let len = string.len() as isize;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let end = if end < 0 {
+            (len + end).max(0)
+        } else {
+            end.min(len - 1)
+        };
+
+        if start > end || len == 0 {
+            return Ok(vec![]);
+        }
+
+        Ok(string[start as usize..=end as usize].to_vec())
+    }
+
+    /// Overwrite the string starting at offset, padding it with zero bytes if it's
+    /// shorter than offset. Returns the new length of the string
+    pub fn setrange(
+        &mut self,
+        key: Key,
+        offset: usize,
+        bytes: Vec<u8>,
+    ) -> Result<usize, StorageError> {
+        let value = match self.get_raw_mut(&key) {
+            Some(value) => value,
+            // Redis doesn't create the key for an empty value
+            None if bytes.is_empty() => return Ok(0),
+            None => {
+                self.set(key.clone(), Vec::<u8>::new());
+                self.get_raw_mut(&key).unwrap()
+            }
+        };
+
+        let string = value
+            .as_string_bytes_mut()
+            .ok_or(StorageError::WrongOperationType)?;
+
+        if bytes.is_empty() {
+            return Ok(string.len());
+        }
+
+        let end = offset + bytes.len();
+        if string.len() < end {
+            string.resize(end, 0);
+        }
+
+        string[offset..end].copy_from_slice(&bytes);
+        let len = string.len();
+        self.touch(&key);
+        Ok(len)
+    }
+
+    /// Approximate number of bytes used by a key and its value
+    pub fn memory_usage(&self, key: &Key, samples: usize) -> Option<usize> {
+        let now = self.now();
+        match self.hash_map.get_key_value(&key.0) {
+            Some((_, value)) if value.is_expired(now) => None,
+            Some((key, value)) => Some(entry_size(key, value, samples)),
+            None => None,
+        }
+    }
+
+    /// Number of keys and their total memory usage
+    pub fn memory_stats(&self) -> (usize, usize) {
+        let now = self.now();
+        self.hash_map
+            .iter()
+            .filter(|(_, value)| !value.is_expired(now))
+            .fold((0, 0), |(keys, bytes), (key, value)| {
+                (keys + 1, bytes + entry_size(key, value, 0))
+            })
+    }
+
+    /// Number of keys and how many of them have an expiration, for INFO keyspace
+    pub fn stats(&self) -> (usize, usize) {
+        let now = self.now();
+        self.hash_map
+            .values()
+            .filter(|value| !value.is_expired(now))
+            .fold((0, 0), |(keys, volatile_keys), value| {
+                (keys + 1, volatile_keys + value.1.is_some() as usize)
+            })
+    }
+
+    pub fn encoding(&self, key: &Key) -> Option<&'static str> {
+        self.get_raw(key)
+            .map(|value| value.encoding(self.list_max_listpack_size))
+    }
+
+    pub fn list_max_listpack_size(&self) -> usize {
+        self.list_max_listpack_size
+    }
+
+    /// Only affects the reported encoding, lists are stored the same way whatever their size
+    pub fn set_list_max_listpack_size(&mut self, list_max_listpack_size: usize) {
+        self.list_max_listpack_size = list_max_listpack_size;
+    }
+
+    /// Low level details about a key, in the format of DEBUG OBJECT
+    pub fn debug_object(&self, key: &Key) -> Option<String> {
+        self.get_raw(key).map(|value| {
+            format!(
+                "Value at:{value:p} refcount:1 encoding:{} serializedlength:{}",
+                value.encoding(self.list_max_listpack_size),
+                value.serialized_length()
+            )
+        })
+    }
+
     fn get_raw(&self, key: &Key) -> Option<&StorageValue> {
-        let now = Instant::now();
+        let now = self.now();
         match self.hash_map.get(&key.0) {
-            Some(ValueWithExpiration(_, Some(exp))) if *exp > now => None,
-            Some(ValueWithExpiration(value, _)) => Some(value),
+            Some(value) if value.is_expired(now) => None,
+            Some(ValueWithExpiration(value, _, _)) => Some(value),
             None => None,
         }
     }
 
     fn get_raw_mut(&mut self, key: &Key) -> Option<&mut StorageValue> {
-        let now = Instant::now();
+        let now = self.now();
         match self.hash_map.get_mut(&key.0) {
-            Some(ValueWithExpiration(_, Some(exp))) if *exp > now => None,
-            Some(ValueWithExpiration(value, _)) => Some(value),
+            Some(value) if value.is_expired(now) => None,
+            Some(ValueWithExpiration(value, _, _)) => Some(value),
             None => None,
         }
     }
@@ -191,12 +608,38 @@ impl Storage {
                     }
                 }
 
-                Ok(list.len())
+                let len = list.len();
+                self.touch(&key);
+                Ok(len)
             }
             _ => Err(StorageError::WrongOperationType),
         }
     }
 
+    /// Same as push, then the list is trimmed to at most max elements by
+    /// dropping from the opposite end, so the most recent values are kept
+    pub fn push_capped(
+        &mut self,
+        key: Key,
+        values: Vec<Vec<u8>>,
+        list_end: ListEnd,
+        max: usize,
+    ) -> Result<usize, StorageError> {
+        let len = self.push(key.clone(), values, list_end)?;
+        if len <= max {
+            return Ok(len);
+        }
+
+        if let Some(StorageValue::List(list)) = self.get_raw_mut(&key) {
+            match list_end {
+                ListEnd::Front => list.truncate(max),
+                ListEnd::Back => drop(list.drain(..len - max)),
+            }
+        }
+
+        Ok(max)
+    }
+
     pub fn pop(
         &mut self,
         key: Key,
@@ -224,6 +667,7 @@ impl Storage {
                 if values.is_empty() {
                     Ok(None)
                 } else {
+                    self.touch(&key);
                     Ok(Some(values))
                 }
             }
@@ -231,51 +675,132 @@ impl Storage {
         }
     }
 
+    /// Keys which are expired but haven't been swept yet are counted, same as Redis
+    pub fn key_count(&self) -> usize {
+        self.hash_map.len()
+    }
+
+    /// A random key among those with an expiration, like RANDOMKEY but
+    /// skipping persistent keys. None if every key is persistent
+    pub fn random_volatile_key(&self) -> Option<Key> {
+        let now = self.now();
+        let volatile_keys = || {
+            self.hash_map
+                .iter()
+                .filter(move |(_, value)| value.1.is_some() && !value.is_expired(now))
+                .map(|(key, _)| key)
+        };
+
+        // One pass to count the candidates, another to skip to the chosen one
+        let count = volatile_keys().count();
+        if count == 0 {
+            return None;
+        }
+
+        let key = volatile_keys().nth(random_number() % count)?;
+        Some(Key(key.clone()))
+    }
+
+    // Keys which are expired but haven't been swept yet are removed too, but
+    // don't count as deleted since they were already gone
     pub fn delete(&mut self, key: &Key) -> bool {
-        self.hash_map.remove(&key.0).is_some()
+        let now = self.now();
+        self.hash_map
+            .remove(&key.0)
+            .is_some_and(|value| !value.is_expired(now))
     }
 
+    // Keys which are expired but haven't been swept yet are considered absent
     pub fn is_exist(&self, key: &Key) -> bool {
-        self.hash_map.contains_key(&key.0)
+        let now = self.now();
+        match self.hash_map.get(&key.0) {
+            Some(value) => !value.is_expired(now),
+            None => false,
+        }
     }
 
     pub fn is_expire(&self, key: &Key) -> Option<bool> {
-        let now = Instant::now();
-        match self.hash_map.get(&key.0) {
-            Some(ValueWithExpiration(_, Some(exp))) if exp <= &now => Some(true),
-            Some(ValueWithExpiration(_, _)) => Some(false),
-            None => None,
-        }
+        let now = self.now();
+        self.hash_map.get(&key.0).map(|value| value.is_expired(now))
     }
 
     pub fn expire(&mut self, key: &Key, ttl: u64) {
-        let exp = Instant::now()
+        let instant = Instant::now()
             .checked_add(Duration::from_millis(ttl))
             .unwrap();
+        let unix_time_ms = now_unix_time_ms().saturating_add(ttl);
 
+        self.set_expiration(
+            key,
+            Expiration {
+                instant,
+                unix_time_ms,
+            },
+        );
+    }
+
+    /// Same as expire, but with a Unix time in milliseconds. A time in the
+    /// past deletes the key right away
+    pub fn expire_at(&mut self, key: &Key, unix_time_ms: u64) {
+        let now = now_unix_time_ms();
+        if unix_time_ms <= now {
+            self.delete(key);
+            return;
+        }
+
+        let instant = Instant::now()
+            .checked_add(Duration::from_millis(unix_time_ms - now))
+            .unwrap();
+
+        self.set_expiration(
+            key,
+            Expiration {
+                instant,
+                unix_time_ms,
+            },
+        );
+    }
+
+    fn set_expiration(&mut self, key: &Key, expiration: Expiration) {
         match self.hash_map.get_mut(&key.0) {
-            Some(value) => value.1 = Some(exp.clone()),
+            Some(value) => value.1 = Some(expiration),
             None => (),
         }
+        self.touch(key);
 
         self.key_expiration_queue
-            .push(KeyWithExpiration(key.clone(), exp));
+            .push(KeyWithExpiration(key.clone(), expiration.instant));
     }
 
+    /// Unix time in milliseconds at which the key expires. -1 if the key
+    /// has no expiration, -2 if it doesn't exist
+    pub fn expire_time(&self, key: &Key) -> isize {
+        let now = self.now();
+        match self.hash_map.get(&key.0) {
+            Some(value) if value.is_expired(now) => -2,
+            Some(ValueWithExpiration(_, Some(exp), _)) => exp.unix_time_ms as isize,
+            Some(ValueWithExpiration(_, None, _)) => -1,
+            None => -2,
+        }
+    }
+
+    // In milliseconds, -1 for a key without expiration and -2 for a missing key
     pub fn ttl(&self, key: &Key) -> isize {
+        let now = self.now();
         match self.hash_map.get(&key.0) {
-            Some(ValueWithExpiration(_, Some(exp))) => {
-                let ttl = exp.duration_since(Instant::now());
-                ttl.as_secs() as isize
+            Some(value) if value.is_expired(now) => -2,
+            Some(ValueWithExpiration(_, Some(exp), _)) => {
+                let ttl = exp.instant.duration_since(now);
+                ttl.as_millis() as isize
             }
-            Some(ValueWithExpiration(_, None)) => -1,
+            Some(ValueWithExpiration(_, None, _)) => -1,
             None => -2,
         }
     }
 
     pub fn scan_expired_keys(&mut self) -> Vec<Key> {
         let mut keys = vec![];
-        let now = Instant::now();
+        let now = self.now();
 
         loop {
             match self.key_expiration_queue.peek() {
@@ -294,10 +819,12 @@ impl Storage {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::storage::{ListEnd, StorageValue};
+    use crate::core::storage::{ListEnd, StorageError, StorageValue};
 
     use super::super::Key;
     use super::Storage;
+    use std::collections::HashSet;
+    use std::mem::size_of;
     use std::thread;
     use std::time::Duration;
 
@@ -324,9 +851,136 @@ mod tests {
         let result = storage.incr(&key, -3).unwrap();
         assert_eq!(result, 0);
 
-        storage.set(key.clone(), "abc");
+        storage.set(key.clone(), "10");
+        let result = storage.incr(&key, 1).unwrap();
+        assert_eq!(result, 11);
+        assert_eq!(storage.get(&key).unwrap(), Some(&StorageValue::Integer(11)));
+
+        for value in ["abc", "010", " 1"] {
+            storage.set(key.clone(), value);
+            let result = storage.incr(&key, 1);
+            assert!(matches!(result, Err(StorageError::NotInteger)));
+        }
+
+        storage.set(key.clone(), vec![b"a".to_vec()]);
         let result = storage.incr(&key, 1);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(StorageError::WrongOperationType)));
+    }
+
+    #[test]
+    fn get_wrong_type() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        // List
+        storage.set(key.clone(), vec![b"a".to_vec()]);
+        let result = storage.get(&key);
+        assert!(matches!(result, Err(StorageError::WrongOperationType)));
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        let result = storage.get_and_set(key.clone(), "a").unwrap();
+        assert_eq!(result, None);
+        let result = storage.get_and_set(key.clone(), "b").unwrap();
+        assert_eq!(result, Some(StorageValue::String(b"a".to_vec())));
+        assert_eq!(
+            storage.get(&key).unwrap(),
+            Some(&StorageValue::String(b"b".to_vec()))
+        );
+
+        // The expiration is dropped, as with SET
+        storage.expire(&key, 10_000);
+        storage.get_and_set(key.clone(), "c").unwrap();
+        assert_eq!(storage.ttl(&key), -1);
+
+        // An expired value counts as missing
+        storage.expire(&key, 0);
+        let result = storage.get_and_set(key.clone(), "d").unwrap();
+        assert_eq!(result, None);
+
+        storage.set(key.clone(), vec![b"a".to_vec()]);
+        let result = storage.get_and_set(key.clone(), "e");
+        assert!(matches!(result, Err(StorageError::WrongOperationType)));
+        assert_eq!(storage.encoding(&key), Some("listpack"));
+    }
+
+    #[test]
+    fn random_volatile_key() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.random_volatile_key(), None);
+
+        for i in 0..10 {
+            storage.set(Key(format!("persistent:{i}").into_bytes()), "a");
+        }
+        assert_eq!(storage.random_volatile_key(), None);
+
+        for i in 0..10 {
+            let key = Key(format!("volatile:{i}").into_bytes());
+            storage.set(key.clone(), "a");
+            storage.expire(&key, 100_000);
+        }
+
+        let mut sampled = HashSet::new();
+        for _ in 0..200 {
+            let key = storage.random_volatile_key().unwrap();
+            assert!(key.0.starts_with(b"volatile:"));
+            sampled.insert(key.0);
+        }
+        // Not always the same key
+        assert!(sampled.len() > 1);
+
+        // Expired keys are not sampled even if they haven't been swept yet
+        let key = Key(b"expired".to_vec());
+        storage.set(key.clone(), "a");
+        storage.expire(&key, 0);
+        for _ in 0..200 {
+            assert_ne!(storage.random_volatile_key(), Some(key.clone()));
+        }
+    }
+
+    #[test]
+    fn get_and_delete() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        let result = storage.get_and_delete(&key).unwrap();
+        assert_eq!(result, None);
+
+        storage.set(key.clone(), 12_isize);
+        let result = storage.get_and_delete(&key).unwrap();
+        assert_eq!(result, Some(StorageValue::Integer(12)));
+        assert!(!storage.is_exist(&key));
+
+        storage.set(key.clone(), "a");
+        storage.expire(&key, 0);
+        let result = storage.get_and_delete(&key).unwrap();
+        assert_eq!(result, None);
+
+        storage.set(key.clone(), vec![b"a".to_vec()]);
+        let result = storage.get_and_delete(&key);
+        assert!(matches!(result, Err(StorageError::WrongOperationType)));
+        assert!(storage.is_exist(&key));
+    }
+
+    #[test]
+    fn incr_overflow() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        let result = storage.incr(&key, isize::MAX - 1).unwrap();
+        assert_eq!(result, isize::MAX - 1);
+        let result = storage.incr(&key, 1).unwrap();
+        assert_eq!(result, isize::MAX);
+
+        let result = storage.incr(&key, 1);
+        assert!(matches!(result, Err(StorageError::Overflow)));
+        // The value is left untouched
+        let result = storage.get(&key).unwrap();
+        assert_eq!(result, Some(&StorageValue::Integer(isize::MAX)));
     }
 
     #[test]
@@ -368,6 +1022,80 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn push_capped() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        for value in ["a", "b", "c", "d", "e"] {
+            storage
+                .push_capped(
+                    key.clone(),
+                    vec![value.as_bytes().to_vec()],
+                    ListEnd::Front,
+                    3,
+                )
+                .unwrap();
+        }
+        let result = storage.pop(key.clone(), 5, ListEnd::Front).unwrap();
+        assert_eq!(
+            result,
+            Some(vec![b"e".to_vec(), b"d".to_vec(), b"c".to_vec()])
+        );
+
+        let values = ["a", "b", "c", "d", "e"].map(|value| value.as_bytes().to_vec());
+        let result = storage.push_capped(key.clone(), values.to_vec(), ListEnd::Back, 3);
+        assert_eq!(result.unwrap(), 3);
+        let result = storage.pop(key.clone(), 5, ListEnd::Front).unwrap();
+        assert_eq!(
+            result,
+            Some(vec![b"c".to_vec(), b"d".to_vec(), b"e".to_vec()])
+        );
+    }
+
+    #[test]
+    fn push_onto_integer() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        storage.set(key.clone(), 12_isize);
+        let result = storage.push(key.clone(), vec![b"a".to_vec()], ListEnd::Front);
+        assert!(matches!(result, Err(StorageError::WrongOperationType)));
+
+        // An expired key is gone, pushing onto it creates a fresh list like Redis does.
+        // This is not a missing WRONGTYPE check
+        storage.expire(&key, 100);
+        thread::sleep(Duration::from_millis(200));
+        let result = storage.push(key.clone(), vec![b"a".to_vec()], ListEnd::Front);
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(storage.encoding(&key), Some("listpack"));
+        assert_eq!(storage.ttl(&key), -1);
+    }
+
+    #[test]
+    fn version() {
+        let mut storage = Storage::new();
+        let key = Key("key".as_bytes().to_vec());
+        assert_eq!(storage.version(&key), 0);
+
+        storage.set(key.clone(), "1");
+        storage.incr(&key, 1).unwrap();
+        assert_eq!(storage.version(&key), 2);
+
+        // Failed and read-only operations don't count as modifications
+        assert!(storage.push(key.clone(), vec![], ListEnd::Back).is_err());
+        storage.getrange(&key, 0, -1).unwrap();
+        assert_eq!(storage.version(&key), 2);
+
+        storage.delete(&key);
+        assert_eq!(storage.version(&key), 0);
+
+        storage.set(key.clone(), "1");
+        storage.flush();
+        storage.set(key.clone(), "1");
+        assert_eq!(storage.version(&key), 4);
+    }
+
     #[test]
     fn expire() {
         let mut storage = Storage::new();
@@ -385,6 +1113,189 @@ mod tests {
         assert_eq!(storage.scan_expired_keys(), vec![key2]);
     }
 
+    #[test]
+    fn append() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        assert_eq!(storage.append(key.clone(), b"abc".to_vec()).unwrap(), 3);
+        assert_eq!(storage.append(key.clone(), b"def".to_vec()).unwrap(), 6);
+        assert_eq!(
+            storage.get(&key).unwrap(),
+            Some(&StorageValue::String(b"abcdef".to_vec()))
+        );
+
+        storage.set(key.clone(), 12_isize);
+        assert_eq!(storage.append(key.clone(), b"3".to_vec()).unwrap(), 3);
+        assert_eq!(
+            storage.get(&key).unwrap(),
+            Some(&StorageValue::String(b"123".to_vec()))
+        );
+
+        storage.set(key.clone(), vec![b"a".to_vec()]);
+        assert!(storage.append(key.clone(), b"b".to_vec()).is_err());
+    }
+
+    #[test]
+    fn getrange_setrange_strlen() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        assert_eq!(storage.strlen(&key).unwrap(), 0);
+        assert_eq!(storage.getrange(&key, 0, -1).unwrap(), b"");
+        assert_eq!(storage.setrange(key.clone(), 3, vec![]).unwrap(), 0);
+        assert!(!storage.is_exist(&key));
+
+        storage.set(key.clone(), b"Hello World".to_vec());
+        assert_eq!(storage.getrange(&key, 0, 4).unwrap(), b"Hello");
+        assert_eq!(storage.getrange(&key, -5, -1).unwrap(), b"World");
+        assert_eq!(storage.getrange(&key, 6, 100).unwrap(), b"World");
+        assert_eq!(storage.getrange(&key, 5, 2).unwrap(), b"");
+
+        assert_eq!(
+            storage.setrange(key.clone(), 6, b"Redis".to_vec()).unwrap(),
+            11
+        );
+        assert_eq!(storage.getrange(&key, 0, -1).unwrap(), b"Hello Redis");
+
+        // Pad with zero bytes
+        let other = Key(b"other".to_vec());
+        assert_eq!(
+            storage.setrange(other.clone(), 2, b"a".to_vec()).unwrap(),
+            3
+        );
+        assert_eq!(storage.getrange(&other, 0, -1).unwrap(), b"\0\0a");
+
+        storage.set(key.clone(), vec![b"a".to_vec()]);
+        assert!(storage.strlen(&key).is_err());
+        assert!(storage.getrange(&key, 0, -1).is_err());
+        assert!(storage.setrange(key.clone(), 0, b"b".to_vec()).is_err());
+    }
+
+    #[test]
+    fn string_commands_on_integer() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        storage.set(key.clone(), 12345_isize);
+        assert_eq!(storage.strlen(&key).unwrap(), 5);
+        assert_eq!(storage.getrange(&key, 0, 2).unwrap(), b"123");
+
+        assert_eq!(storage.setrange(key.clone(), 0, b"9".to_vec()).unwrap(), 5);
+        assert_eq!(
+            storage.get(&key).unwrap(),
+            Some(&StorageValue::String(b"92345".to_vec()))
+        );
+    }
+
+    #[test]
+    fn encoding() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+        assert_eq!(storage.encoding(&key), None);
+
+        storage.set(key.clone(), 12_isize);
+        assert_eq!(storage.encoding(&key), Some("int"));
+        storage.set(key.clone(), "12");
+        assert_eq!(storage.encoding(&key), Some("int"));
+        storage.set(key.clone(), "012");
+        assert_eq!(storage.encoding(&key), Some("embstr"));
+        storage.set(key.clone(), "a".repeat(45).as_str());
+        assert_eq!(storage.encoding(&key), Some("raw"));
+
+        storage.set(key.clone(), vec![b"a".to_vec(); 128]);
+        assert_eq!(storage.encoding(&key), Some("listpack"));
+        storage.set(key.clone(), vec![b"a".to_vec(); 129]);
+        assert_eq!(storage.encoding(&key), Some("quicklist"));
+
+        storage.set_list_max_listpack_size(200);
+        assert_eq!(storage.encoding(&key), Some("listpack"));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+        assert_eq!(storage.memory_usage(&key, 0), None);
+
+        storage.set(key.clone(), "a");
+        let small = storage.memory_usage(&key, 0).unwrap();
+        storage.set(key.clone(), "a".repeat(100).as_str());
+        let large = storage.memory_usage(&key, 0).unwrap();
+        assert!(large >= small + 99);
+
+        storage.set(key.clone(), vec![b"a".to_vec(); 10]);
+        let short_list = storage.memory_usage(&key, 0).unwrap();
+        storage.set(key.clone(), vec![b"a".to_vec(); 20]);
+        let long_list = storage.memory_usage(&key, 0).unwrap();
+        assert!(long_list >= short_list + 10 * (size_of::<Vec<u8>>() + 1));
+    }
+
+    #[test]
+    fn memory_usage_samples() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+
+        // Items of the same size are estimated exactly
+        storage.set(key.clone(), vec![b"a".repeat(10); 10_000]);
+        let full = storage.memory_usage(&key, 0).unwrap();
+        assert_eq!(storage.memory_usage(&key, 5).unwrap(), full);
+        assert_eq!(storage.memory_usage(&key, 20_000).unwrap(), full);
+
+        // Only the first items are sampled
+        let mut list = vec![b"a".repeat(1_000); 5];
+        list.extend(vec![b"a".repeat(10); 9_995]);
+        storage.set(key.clone(), list);
+        let full = storage.memory_usage(&key, 0).unwrap();
+        let sampled = storage.memory_usage(&key, 5).unwrap();
+        assert!(sampled > full + 9_000_000, "{sampled} {full}");
+    }
+
+    #[test]
+    fn memory_stats() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.memory_stats(), (0, 0));
+
+        let key1 = Key(b"key1".to_vec());
+        let key2 = Key(b"key2".to_vec());
+        storage.set(key1.clone(), "a");
+        storage.set(key2.clone(), vec![b"a".to_vec()]);
+
+        let total =
+            storage.memory_usage(&key1, 0).unwrap() + storage.memory_usage(&key2, 0).unwrap();
+        assert_eq!(storage.memory_stats(), (2, total));
+    }
+
+    #[test]
+    fn stats() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.stats(), (0, 0));
+
+        let persistent = Key(b"persistent".to_vec());
+        let volatile = Key(b"volatile".to_vec());
+        let expired = Key(b"expired".to_vec());
+        storage.set(persistent, "a");
+        storage.set(volatile.clone(), "a");
+        storage.expire(&volatile, 100_000);
+        storage.set(expired.clone(), "a");
+        storage.expire(&expired, 0);
+
+        assert_eq!(storage.stats(), (2, 1));
+    }
+
+    #[test]
+    fn is_exist() {
+        let mut storage = Storage::new();
+        let key = Key(b"key".to_vec());
+        assert!(!storage.is_exist(&key));
+
+        storage.set(key.clone(), "hello");
+        assert!(storage.is_exist(&key));
+
+        storage.expire(&key, 0);
+        assert!(!storage.is_exist(&key));
+    }
+
     #[test]
     fn ttl() {
         let mut storage = Storage::new();
@@ -395,10 +1306,48 @@ mod tests {
         assert_eq!(storage.ttl(&key), -1);
 
         storage.expire(&key, 2_500);
-        assert_eq!(storage.ttl(&key), 2);
+        assert_eq!(storage.ttl(&key) / 1000, 2);
 
         thread::sleep(Duration::from_secs(2));
         let result = storage.get(&key).unwrap();
+        assert!(result.is_some());
+
+        thread::sleep(Duration::from_secs(1));
+        let result = storage.get(&key).unwrap();
         assert!(result.is_none());
+        assert_eq!(storage.ttl(&key), -2);
+        assert!(!storage.is_exist(&key));
+    }
+
+    #[test]
+    fn coarse_clock() {
+        let mut storage = Storage::new();
+        storage.set_coarse_clock(true);
+        let key = Key(b"key".to_vec());
+
+        storage.set(key.clone(), "hello");
+        storage.expire(&key, 50);
+        thread::sleep(Duration::from_millis(100));
+
+        // The key outlives its expiration until the next tick
+        assert!(storage.is_exist(&key));
+        assert_eq!(storage.is_expire(&key), Some(false));
+        assert!(storage.memory_usage(&key, 0).is_some());
+        assert_eq!(storage.memory_stats().0, 1);
+        assert!(storage.scan_expired_keys().is_empty());
+
+        storage.tick();
+        assert!(!storage.is_exist(&key));
+        assert!(storage.get(&key).unwrap().is_none());
+        assert_eq!(storage.is_expire(&key), Some(true));
+        assert!(storage.memory_usage(&key, 0).is_none());
+        assert_eq!(storage.memory_stats().0, 0);
+        assert_eq!(storage.scan_expired_keys(), vec![key.clone()]);
+
+        storage.set_coarse_clock(false);
+        storage.set(key.clone(), "hello");
+        storage.expire(&key, 50);
+        thread::sleep(Duration::from_millis(100));
+        assert!(!storage.is_exist(&key));
     }
 }
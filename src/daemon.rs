@@ -0,0 +1,24 @@
+/// This module detaches the server from the terminal, the Unix way: fork, start
+/// a new session and leave a pidfile behind so service managers can find the process
+use std::{fs, io, process};
+
+use nix::unistd;
+
+/// Must be called before spawning any thread, only the calling thread
+/// survives the fork
+pub fn daemonize() -> io::Result<()> {
+    // Keep the working directory, so relative paths in the config still work
+    unistd::daemon(true, false)?;
+    Ok(())
+}
+
+/// Write the pid of the current process to path
+pub fn write_pidfile(path: &str) -> io::Result<()> {
+    fs::write(path, format!("{}\n", process::id()))
+}
+
+pub fn remove_pidfile(path: &str) {
+    if let Err(error) = fs::remove_file(path) {
+        log::warn!("Fail to remove pidfile {path}: {error}");
+    }
+}
@@ -1,9 +1,12 @@
 use super::JobQueue;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 
 pub struct Queue<T> {
     sender: mpsc::Sender<T>,
     receiver: Option<mpsc::Receiver<T>>,
+    // The channel doesn't expose its length, so we count the items ourselves
+    len: Arc<AtomicUsize>,
 }
 
 impl<T> Clone for Queue<T> {
@@ -11,6 +14,7 @@ impl<T> Clone for Queue<T> {
         Self {
             sender: self.sender.clone(),
             receiver: None,
+            len: self.len.clone(),
         }
     }
 }
@@ -21,22 +25,31 @@ impl<T> Queue<T> {
         Self {
             sender,
             receiver: Some(receiver),
+            len: Arc::new(AtomicUsize::new(0)),
         }
     }
 }
 
 impl<T> JobQueue<T> for Queue<T> {
     fn enqueue(&self, item: T) -> () {
+        // Count before sending, so the counter never goes below zero
+        self.len.fetch_add(1, Ordering::Relaxed);
         self.sender.send(item).unwrap();
     }
 
     fn dequeue(&self) -> T {
         if let Some(receiver) = &self.receiver {
-            receiver.recv().unwrap()
+            let item = receiver.recv().unwrap();
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            item
         } else {
             panic!("Cloned channel_queue::Queue can't not dequeue items")
         }
     }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
@@ -58,6 +71,20 @@ mod tests {
         assert_eq!(queue.dequeue(), 3);
     }
 
+    #[test]
+    fn len() {
+        let queue = Queue::new();
+        assert_eq!(queue.len(), 0);
+
+        // Items enqueued through clones are counted too
+        queue.enqueue(1);
+        queue.clone().enqueue(2);
+        assert_eq!(queue.len(), 2);
+
+        queue.dequeue();
+        assert_eq!(queue.len(), 1);
+    }
+
     #[test]
     fn multi_thread() {
         let queue = Queue::new();
@@ -39,6 +39,10 @@ impl<T> JobQueue<T> for Queue<T> {
             }
         }
     }
+
+    fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
 }
 
 #[cfg(test)]
@@ -60,6 +64,19 @@ mod tests {
         assert_eq!(queue.dequeue(), 3);
     }
 
+    #[test]
+    fn len() {
+        let queue = Queue::new();
+        assert_eq!(queue.len(), 0);
+
+        queue.enqueue(1);
+        queue.enqueue(2);
+        assert_eq!(queue.len(), 2);
+
+        queue.dequeue();
+        assert_eq!(queue.len(), 1);
+    }
+
     #[test]
     fn multi_thread() {
         let queue = Queue::new();
@@ -1,8 +1,8 @@
 use super::JobQueue;
 use std::cell::SyncUnsafeCell;
-use std::fmt::Debug;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
 
 /**
  * Disruptor uses a ring buffer to mimic a queue. Ring buffer is a vec, except
@@ -22,30 +22,45 @@ use std::sync::Arc;
  * The slot is the remainder of sequence number divided by the size, in this case 4 % 4 = 0.
  * This calculation is trivial if the size is a exponential of 2. In the above example, the slot is
  * the last two bits of the sequence number
+ *
+ * Sequences only ever increase, but they may wrap around usize::MAX. All the cursor
+ * math uses wrapping operations: the distance between two cursors stays correct
+ * across the wrap as long as it's smaller than usize::MAX, which the ring size
+ * guarantees. Slots stay correct as well, since the size divides usize::MAX + 1
+ *
+ * Each slot holds an Option<T>. Dequeue takes the item out and leaves None behind, so items
+ * which are never dequeued are dropped together with the ring when the last clone of the
+ * queue goes away.
 */
 
 pub struct Queue<T, const SIZE: usize> {
     head_cursor: Arc<AtomicUsize>,
     tail_cursor: Arc<AtomicUsize>,
     next_slot_cursor: Arc<AtomicUsize>,
-    ring: Arc<SyncUnsafeCell<[Option<T>; SIZE]>>,
+    ring: Arc<SyncUnsafeCell<Box<[Option<T>]>>>,
     size: usize,
     exponential: i8,
 }
 
-impl<T: Copy, const SIZE: usize> Queue<T, SIZE> {
+impl<T, const SIZE: usize> Queue<T, SIZE> {
     pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// Start the cursors at an arbitrary sequence, tests use it to cross the wrap
+    fn starting_at(sequence: usize) -> Self {
         Self {
-            // The last item we dequeue (0 means we haven't dequeue any items)
-            head_cursor: Arc::new(AtomicUsize::new(0)),
+            // The last item we dequeue (the starting sequence means we haven't dequeue any items)
+            head_cursor: Arc::new(AtomicUsize::new(sequence)),
 
-            // The last item we enqueue (0 means we haven't enqueue any items)
-            tail_cursor: Arc::new(AtomicUsize::new(0)),
+            // The last item we enqueue (the starting sequence means we haven't enqueue any items)
+            tail_cursor: Arc::new(AtomicUsize::new(sequence)),
 
             // The next sequence we will enqueue
-            next_slot_cursor: Arc::new(AtomicUsize::new(1)),
+            next_slot_cursor: Arc::new(AtomicUsize::new(sequence.wrapping_add(1))),
 
-            ring: Arc::new(SyncUnsafeCell::new([None; SIZE])),
+            // Allocate the ring on the heap, it may be too big to fit in the stack
+            ring: Arc::new(SyncUnsafeCell::new((0..SIZE).map(|_| None).collect())),
             size: SIZE,
             exponential: (SIZE as f64).log2() as i8,
         }
@@ -54,13 +69,18 @@ impl<T: Copy, const SIZE: usize> Queue<T, SIZE> {
     // We have to make sure we don't overflow the ring by over-claim the sequence
     fn claim_sequence(&self) -> usize {
         loop {
-            // Spin loop
-            while self.is_full() {}
-
             let current = self.next_slot_cursor.load(Ordering::Acquire);
+
+            // Spin loop. Yield while spinning so we still make progress when
+            // there are more producers than CPU cores
+            if self.is_full(current) {
+                thread::yield_now();
+                continue;
+            }
+
             match self.next_slot_cursor.compare_exchange(
                 current,
-                current + 1,
+                current.wrapping_add(1),
                 Ordering::AcqRel,
                 Ordering::Relaxed,
             ) {
@@ -74,10 +94,13 @@ impl<T: Copy, const SIZE: usize> Queue<T, SIZE> {
         ((1 << self.exponential) - 1) & sequence
     }
 
-    fn is_full(&self) -> bool {
-        let next_slot = self.next_slot_cursor.load(Ordering::Acquire);
+    /// Whether claiming the given sequence would overwrite an item which
+    /// hasn't been dequeued yet. The sequence must be read before the head_cursor:
+    /// the head_cursor never passes a sequence that has been claimed, so reading in
+    /// this order guarantees head is behind sequence and the distance is positive
+    fn is_full(&self, sequence: usize) -> bool {
         let head = self.head_cursor.load(Ordering::Acquire);
-        next_slot - head - 1 == self.size
+        sequence.wrapping_sub(head) > self.size
     }
 
     fn is_empty(&self) -> bool {
@@ -94,17 +117,19 @@ impl<T: Copy, const SIZE: usize> Queue<T, SIZE> {
         while self
             .tail_cursor
             .compare_exchange(
-                new_tail_cursor - 1,
+                new_tail_cursor.wrapping_sub(1),
                 new_tail_cursor,
                 Ordering::SeqCst,
                 Ordering::SeqCst,
             )
             .is_err()
-        {}
+        {
+            thread::yield_now();
+        }
     }
 }
 
-impl<T: Copy, const SIZE: usize> Clone for Queue<T, SIZE> {
+impl<T, const SIZE: usize> Clone for Queue<T, SIZE> {
     fn clone(&self) -> Self {
         Self {
             head_cursor: self.head_cursor.clone(),
@@ -117,7 +142,7 @@ impl<T: Copy, const SIZE: usize> Clone for Queue<T, SIZE> {
     }
 }
 
-impl<T: Copy + Debug, const SIZE: usize> JobQueue<T> for Queue<T, SIZE> {
+impl<T, const SIZE: usize> JobQueue<T> for Queue<T, SIZE> {
     fn enqueue(&self, item: T) -> () {
         // Claim phase: we only increment next_slot_cursor in this phase
         let next_sequence = self.claim_sequence();
@@ -135,18 +160,32 @@ impl<T: Copy + Debug, const SIZE: usize> JobQueue<T> for Queue<T, SIZE> {
 
     fn dequeue(&self) -> T {
         // Spin loop
-        while self.is_empty() {}
+        while self.is_empty() {
+            thread::yield_now();
+        }
 
-        // There is only consumer thread which
-        self.head_cursor.fetch_add(1, Ordering::Release);
-        let consume_sequence = self.head_cursor.load(Ordering::Acquire);
+        // There is only one consumer thread, so no one else can move the head_cursor.
+        // We must take the item out before advancing the head_cursor, otherwise
+        // producers may consider the slot free and overwrite it
+        let consume_sequence = self.head_cursor.load(Ordering::Acquire).wrapping_add(1);
         let consume_slot = self.slot_from_sequence(consume_sequence);
 
-        unsafe {
+        let item = unsafe {
             let first_item_ptr = (*self.ring.get()).as_mut_ptr();
             let target_ptr = first_item_ptr.add(consume_slot);
             (*target_ptr).take().unwrap()
-        }
+        };
+
+        self.head_cursor.store(consume_sequence, Ordering::Release);
+        item
+    }
+
+    /// Only counts committed items, items which are being written into
+    /// their claimed slots are left out
+    fn len(&self) -> usize {
+        let tail = self.tail_cursor.load(Ordering::Acquire);
+        let head = self.head_cursor.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
     }
 }
 
@@ -154,6 +193,8 @@ impl<T: Copy + Debug, const SIZE: usize> JobQueue<T> for Queue<T, SIZE> {
 mod tests {
     use super::super::JobQueue;
     use super::Queue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use std::thread;
 
     #[test]
@@ -171,6 +212,61 @@ mod tests {
         assert_eq!(queue.dequeue(), 4);
     }
 
+    #[test]
+    fn len() {
+        let queue = Queue::<_, 4>::new();
+        assert_eq!(queue.len(), 0);
+
+        queue.enqueue(1);
+        queue.enqueue(2);
+        queue.enqueue(3);
+        assert_eq!(queue.len(), 3);
+
+        queue.dequeue();
+        queue.enqueue(4);
+        queue.enqueue(5);
+        assert_eq!(queue.len(), 4);
+    }
+
+    #[test]
+    fn non_copy_items() {
+        let queue = Queue::<_, 4>::new();
+
+        queue.enqueue(String::from("hello"));
+        queue.enqueue(String::from("world"));
+
+        assert_eq!(queue.dequeue(), "hello");
+        assert_eq!(queue.dequeue(), "world");
+    }
+
+    #[test]
+    fn drop_leftover_items() {
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let queue = Queue::<_, 4>::new();
+        let clone = queue.clone();
+
+        for _i in 1..=3 {
+            queue.enqueue(DropCounter(counter.clone()));
+        }
+
+        drop(queue.dequeue());
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        // The ring is shared, leftover items live until the last clone is gone
+        drop(queue);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        drop(clone);
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
     #[test]
     fn multi_thread() {
         let queue = Queue::<_, 8>::new();
@@ -197,4 +293,85 @@ mod tests {
         values.sort();
         assert_eq!(values, (1..=200).collect::<Vec<i32>>());
     }
+
+    #[test]
+    fn multi_thread_stress() {
+        const N_THREAD: usize = 4;
+        const N_ITEM: usize = 1_000_000;
+        let queue = Queue::<_, 16>::new();
+
+        for thread_index in 0..N_THREAD {
+            let clone = queue.clone();
+            thread::spawn(move || {
+                for i in 0..N_ITEM {
+                    clone.enqueue(thread_index * N_ITEM + i);
+                }
+            });
+        }
+
+        // Items from the same producer must come out in order
+        let mut last_seen = vec![None; N_THREAD];
+        for _i in 0..N_THREAD * N_ITEM {
+            let value = queue.dequeue();
+            let thread_index = value / N_ITEM;
+
+            if let Some(last) = last_seen[thread_index] {
+                assert!(value > last);
+            }
+            last_seen[thread_index] = Some(value);
+        }
+
+        for (thread_index, last) in last_seen.into_iter().enumerate() {
+            assert_eq!(last, Some(thread_index * N_ITEM + N_ITEM - 1));
+        }
+    }
+
+    #[test]
+    fn cursor_wraparound() {
+        let queue = Queue::<_, 4>::starting_at(usize::MAX - 5);
+
+        // Fill and drain the ring a few times across the wrap
+        let mut next = 0;
+        for _round in 0..4 {
+            for i in 0..4 {
+                queue.enqueue(next + i);
+            }
+            assert_eq!(queue.len(), 4);
+
+            for i in 0..4 {
+                assert_eq!(queue.dequeue(), next + i);
+            }
+            assert_eq!(queue.len(), 0);
+            next += 4;
+        }
+
+        // The ring is full on both sides of the wrap
+        assert!(!queue.is_full(queue.next_slot_cursor.load(Ordering::Acquire)));
+        for i in 0..4 {
+            queue.enqueue(i);
+        }
+        assert!(queue.is_full(queue.next_slot_cursor.load(Ordering::Acquire)));
+    }
+
+    #[test]
+    fn multi_thread_wraparound() {
+        const N_THREAD: usize = 4;
+        const N_ITEM: usize = 10_000;
+        let queue = Queue::<_, 16>::starting_at(usize::MAX - 100);
+
+        for thread_index in 0..N_THREAD {
+            let clone = queue.clone();
+            thread::spawn(move || {
+                for i in 0..N_ITEM {
+                    clone.enqueue(thread_index * N_ITEM + i);
+                }
+            });
+        }
+
+        let mut values = (0..N_THREAD * N_ITEM)
+            .map(|_| queue.dequeue())
+            .collect::<Vec<usize>>();
+        values.sort();
+        assert_eq!(values, (0..N_THREAD * N_ITEM).collect::<Vec<usize>>());
+    }
 }
@@ -9,4 +9,12 @@ pub trait JobQueue<T> {
   // This will block until there's new job to handle
   // hence it will always return a result
   fn dequeue(&self) -> T;
+
+  // Number of enqueued items which haven't been dequeued yet. With concurrent
+  // producers, this is only a snapshot
+  fn len(&self) -> usize;
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
 }
@@ -2,4 +2,7 @@
 pub mod job_queue;
 pub mod server;
 pub mod core;
-pub mod connection;
\ No newline at end of file
+pub mod connection;
+pub mod config;
+#[cfg(feature = "daemon")]
+pub mod daemon;
\ No newline at end of file
@@ -1,22 +1,23 @@
-use tiny_redis::server::Server;
+use std::{env, process};
+use tiny_redis::config;
 
 fn main() {
     env_logger::init();
 
-    // While researching this topic, we found an interesting performance problem from Linux
-    // kernel in the past. This issue is called Thundering herd.
-    // It happens when multiple threads are waiting on accept() call on the same socket.
-    // When the new connection arrives, every threads will be woke up, but only one thread
-    // can grab the connection. This wastes CPU cycles and leads to performance downgrade in
-    // high-load services.
-    //
-    // Some interesting links:
-    // https://uwsgi-docs.readthedocs.io/en/latest/articles/SerializingAccept.html
-    // http://www.citi.umich.edu/projects/linux-scalability/reports/accept.html
-    let endpoint = Server {
-        interface: String::from("127.0.0.1"),
-        port: 7878,
+    let args = env::args().skip(1);
+    let endpoint = match config::parse_args(args).and_then(|builder| builder.build()) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Invalid configuration: {error}");
+            process::exit(1);
+        }
     };
 
+    #[cfg(feature = "signals")]
+    if let Err(error) = endpoint.install_signal_handlers() {
+        eprintln!("Fail to install signal handlers: {error}");
+        process::exit(1);
+    }
+
     endpoint.start();
 }
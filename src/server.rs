@@ -1,101 +1,1042 @@
 use std::{
+    fmt, io,
     io::Write,
-    net::{TcpListener, TcpStream},
+    mem,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     sync::mpsc,
     sync::mpsc::{Receiver, Sender},
+    sync::Arc,
     thread,
 };
 
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use socket2::{Domain, Socket, Type};
+
+use crate::config;
 use crate::connection::{inbound, outbound};
-use crate::core::{Command, Core};
-use crate::job_queue::deque::Queue;
-use crate::job_queue::JobQueue;
+use crate::core::{storage, Command, CommandResponse, Core};
+#[cfg(feature = "daemon")]
+use crate::daemon;
+use crate::job_queue::{channel_queue, deque, disruptor, JobQueue};
+
+// Must be a power of 2
+const DISRUPTOR_SIZE: usize = 1 << 12;
 
 pub struct Server {
     pub interface: String,
     pub port: usize,
+    pub job_queue: JobQueueKind,
+    pub num_acceptors: usize,
+    pub max_clients: usize,
+    // Disable Nagle's algorithm on client connections
+    pub tcp_nodelay: bool,
+    // Length of the queue of connections waiting to be accepted
+    pub tcp_backlog: i32,
+    // Commands with more arguments are rejected with a protocol error
+    pub max_arguments: usize,
+    // A connection sending more invalid commands in a row is closed
+    pub max_consecutive_errors: usize,
+    // Lists are trimmed to this length on push, like a capped list
+    pub list_max_length: Option<usize>,
+    // Lists with more elements are reported with the quicklist encoding
+    pub list_max_listpack_size: usize,
+    // Check expirations against a clock refreshed by the expiration timer,
+    // instead of reading the clock on every read. Keys may outlive their
+    // expiration by up to the timer interval
+    pub coarse_clock: bool,
+    pub daemonize: bool,
+    pub pidfile: Option<String>,
+    // Once set, the server stops accepting connections, answers the commands
+    // which are already queued and start() returns
+    pub shutdown: Arc<AtomicBool>,
+}
+
+/// The job queue implementation which connection threads use to
+/// hand commands over to the main thread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobQueueKind {
+    Deque,
+    Channel,
+    Disruptor,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            interface: String::from("127.0.0.1"),
+            port: 7878,
+            job_queue: JobQueueKind::Deque,
+            num_acceptors: 1,
+            max_clients: 10_000,
+            tcp_nodelay: true,
+            tcp_backlog: 511,
+            max_arguments: inbound::DEFAULT_MAX_ARGUMENTS,
+            max_consecutive_errors: 100,
+            list_max_length: None,
+            list_max_listpack_size: storage::DEFAULT_LIST_MAX_LISTPACK_SIZE,
+            coarse_clock: false,
+            daemonize: false,
+            pidfile: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    InvalidPort(usize),
+    NoAcceptor,
+    NoClient,
+    InvalidValue(String, String),
+    Unsupported(String),
+    IoError(io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidPort(port) => write!(f, "invalid port {port}"),
+            ConfigError::NoAcceptor => write!(f, "at least one acceptor thread is required"),
+            ConfigError::NoClient => write!(f, "max clients must be greater than 0"),
+            ConfigError::InvalidValue(directive, value) => {
+                write!(f, "invalid value {value:?} for {directive}")
+            }
+            ConfigError::Unsupported(directive) => write!(f, "{directive} is not supported"),
+            ConfigError::IoError(error) => write!(f, "fail to read config: {error}"),
+        }
+    }
+}
+
+/// Fluent interface to configure a Server. Options which are not set
+/// take their values from Server::default()
+pub struct ServerBuilder(Server);
+
+impl ServerBuilder {
+    pub fn interface(mut self, interface: &str) -> Self {
+        self.0.interface = String::from(interface);
+        self
+    }
+
+    pub fn port(mut self, port: usize) -> Self {
+        self.0.port = port;
+        self
+    }
+
+    pub fn job_queue(mut self, job_queue: JobQueueKind) -> Self {
+        self.0.job_queue = job_queue;
+        self
+    }
+
+    pub fn num_acceptors(mut self, num_acceptors: usize) -> Self {
+        self.0.num_acceptors = num_acceptors;
+        self
+    }
+
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.0.max_clients = max_clients;
+        self
+    }
+
+    pub fn tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.0.tcp_nodelay = tcp_nodelay;
+        self
+    }
+
+    pub fn tcp_backlog(mut self, tcp_backlog: i32) -> Self {
+        self.0.tcp_backlog = tcp_backlog;
+        self
+    }
+
+    pub fn max_arguments(mut self, max_arguments: usize) -> Self {
+        self.0.max_arguments = max_arguments;
+        self
+    }
+
+    pub fn max_consecutive_errors(mut self, max_consecutive_errors: usize) -> Self {
+        self.0.max_consecutive_errors = max_consecutive_errors;
+        self
+    }
+
+    pub fn list_max_length(mut self, list_max_length: usize) -> Self {
+        self.0.list_max_length = Some(list_max_length);
+        self
+    }
+
+    pub fn list_max_listpack_size(mut self, list_max_listpack_size: usize) -> Self {
+        self.0.list_max_listpack_size = list_max_listpack_size;
+        self
+    }
+
+    pub fn coarse_clock(mut self, coarse_clock: bool) -> Self {
+        self.0.coarse_clock = coarse_clock;
+        self
+    }
+
+    /// Fork into the background before serving. Requires the daemon feature
+    pub fn daemonize(mut self, daemonize: bool) -> Self {
+        self.0.daemonize = daemonize;
+        self
+    }
+
+    /// Where to write the pid of the server. Requires the daemon feature
+    pub fn pidfile(mut self, pidfile: &str) -> Self {
+        self.0.pidfile = Some(String::from(pidfile));
+        self
+    }
+
+    pub fn build(self) -> Result<Server, ConfigError> {
+        let server = self.0;
+
+        if server.port == 0 || server.port > u16::MAX as usize {
+            return Err(ConfigError::InvalidPort(server.port));
+        }
+
+        if server.num_acceptors == 0 {
+            return Err(ConfigError::NoAcceptor);
+        }
+
+        if server.max_clients == 0 {
+            return Err(ConfigError::NoClient);
+        }
+
+        if !cfg!(feature = "daemon") {
+            if server.daemonize {
+                return Err(ConfigError::Unsupported(String::from("daemonize")));
+            }
+
+            if server.pidfile.is_some() {
+                return Err(ConfigError::Unsupported(String::from("pidfile")));
+            }
+        }
+
+        Ok(server)
+    }
 }
 
+// The second field is where the reply goes: the main thread encodes it into the
+// buffer of the connection and sends the buffer back, so a connection reuses
+// the same buffer for all its replies. The last field is the command as MONITOR
+// shows it, only set while a connection is in MONITOR mode
 #[derive(Clone)]
-pub struct CommandWithSender(Command, Option<Sender<Vec<u8>>>);
+pub struct CommandWithSender(Command, Option<(Sender<Vec<u8>>, Vec<u8>)>, Option<String>);
+
+// A buffer which grew larger than this for a big reply isn't kept afterwards
+const MAX_KEPT_REPLY_BUFFER: usize = 64 * 1024;
 
 impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder(Server::default())
+    }
+
+    /// Read a redis.conf-style file. See config::parse_file for the supported directives
+    pub fn from_config_file(path: &str) -> Result<Server, ConfigError> {
+        config::read_file(path)?.build()
+    }
+
+    /// Ask a running server to shut down gracefully, see the shutdown field
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    /// Shut down gracefully on SIGTERM and SIGINT. A second signal terminates the
+    /// process right away, in case the graceful shutdown hangs. Call this before
+    /// start(), so there is no window where a signal kills the process abruptly
+    #[cfg(feature = "signals")]
+    pub fn install_signal_handlers(&self) -> io::Result<()> {
+        use signal_hook::consts::TERM_SIGNALS;
+        use signal_hook::flag;
+
+        for signal in TERM_SIGNALS {
+            // Order matters: the first signal only arms the exit, since the flag is still unset
+            flag::register_conditional_shutdown(*signal, 1, self.shutdown.clone())?;
+            flag::register(*signal, self.shutdown.clone())?;
+        }
+
+        Ok(())
+    }
+
     pub fn start(&self) {
-        let interface = &self.interface;
-        let port = &self.port;
-        let addr = format!("{interface}:{port}");
-        let listener = TcpListener::bind(addr).unwrap();
+        #[cfg(feature = "daemon")]
+        {
+            if self.daemonize {
+                daemon::daemonize().expect("Fail to run in the background");
+            }
+
+            if let Some(pidfile) = &self.pidfile {
+                daemon::write_pidfile(pidfile).expect("Fail to write pidfile");
+            }
+        }
+
+        let start_time = Instant::now();
+        match self.job_queue {
+            JobQueueKind::Deque => self.run(deque::Queue::new(), start_time),
+            JobQueueKind::Channel => self.run(channel_queue::Queue::new(), start_time),
+            JobQueueKind::Disruptor => {
+                self.run(disruptor::Queue::<_, DISRUPTOR_SIZE>::new(), start_time)
+            }
+        }
+
+        #[cfg(feature = "daemon")]
+        if let Some(pidfile) = &self.pidfile {
+            daemon::remove_pidfile(pidfile);
+        }
+
+        log::info!("Server is shut down");
+    }
+
+    fn run<Q>(&self, job_queue: Q, start_time: Instant)
+    where
+        Q: JobQueue<CommandWithSender> + Clone + Send + 'static,
+    {
+        let listener = self.bind().unwrap();
 
         let mut core = Core::new();
-        let job_queue: Queue<CommandWithSender> = Queue::new();
+        core.set_list_max_length(self.list_max_length);
+        core.set_list_max_listpack_size(self.list_max_listpack_size);
+        core.set_coarse_clock(self.coarse_clock);
+        core.set_start_time(start_time);
+
+        // Acceptor threads
+        let client_limit = ClientLimit {
+            connected: Arc::new(AtomicUsize::new(0)),
+            max: self.max_clients,
+        };
 
-        // Acceptor thread
-        let job_queue_clone = job_queue.clone();
-        thread::spawn(move || accept_loop(listener, job_queue_clone));
+        // Connections in MONITOR mode. The count is shared with the connections,
+        // which only keep the commands as MONITOR shows them while it's not zero
+        let mut monitor_senders: Vec<Sender<Vec<u8>>> = vec![];
+        let monitors = Arc::new(AtomicUsize::new(0));
+
+        // While researching this topic, we found an interesting performance problem from Linux
+        // kernel in the past. This issue is called Thundering herd.
+        // It happens when multiple threads are waiting on accept() call on the same socket.
+        // When the new connection arrives, every threads will be woke up, but only one thread
+        // can grab the connection. This wastes CPU cycles and leads to performance downgrade in
+        // high-load services.
+        //
+        // Some interesting links:
+        // https://uwsgi-docs.readthedocs.io/en/latest/articles/SerializingAccept.html
+        // http://www.citi.umich.edu/projects/linux-scalability/reports/accept.html
+        let mut acceptors = vec![];
+        for _i in 0..self.num_acceptors {
+            let listener_clone = listener.try_clone().unwrap();
+            let job_queue_clone = job_queue.clone();
+            let client_limit_clone = client_limit.clone();
+            let acceptor = Acceptor {
+                tcp_nodelay: self.tcp_nodelay,
+                max_arguments: self.max_arguments,
+                max_consecutive_errors: self.max_consecutive_errors,
+                shutdown: self.shutdown.clone(),
+                monitors: monitors.clone(),
+            };
+            acceptors.push(thread::spawn(move || {
+                accept_loop(
+                    listener_clone,
+                    job_queue_clone,
+                    client_limit_clone,
+                    acceptor,
+                )
+            }));
+        }
 
-        // Expiration interval check
+        // Expiration interval check. It also wakes the main thread up regularly,
+        // so it notices the shutdown even without traffic. Hence it enqueues once
+        // more after the shutdown, the main thread may be waiting for it
         let job_queue_clone_1 = job_queue.clone();
+        let shutdown = self.shutdown.clone();
         thread::spawn(move || loop {
-            job_queue_clone_1.enqueue(CommandWithSender(Command::ExpIntervalCheck, None));
+            job_queue_clone_1.enqueue(CommandWithSender(Command::ExpIntervalCheck, None, None));
+            if shutdown.load(Ordering::Acquire) {
+                break;
+            }
+
             thread::sleep(Duration::from_millis(100));
         });
 
-        // Main thread
-        loop {
-            let CommandWithSender(command, sender) = job_queue.dequeue();
+        let mut handle = |CommandWithSender(command, reply_to, monitor_line)| {
+            // Monitors see the command before it's processed, same as Redis
+            if let Some(monitor_line) = monitor_line {
+                let line = timestamped(&monitor_line);
+                monitor_senders.retain(|monitor| monitor.send(line.clone()).is_ok());
+                monitors.store(monitor_senders.len(), Ordering::Release);
+            }
+
+            if let Command::Info = command {
+                core.set_queue_depth(job_queue.len());
+            }
+
+            let monitor = matches!(command, Command::Monitor);
             let response = core.handle_command(command);
-            let response_bytes = outbound::encode(response);
 
-            if let Some(sender) = sender {
-                sender.send(response_bytes).unwrap();
+            if let Some((sender, mut buffer)) = reply_to {
+                // The stream goes through the channel of the MONITOR reply
+                if monitor {
+                    monitor_senders.push(sender.clone());
+                    monitors.store(monitor_senders.len(), Ordering::Release);
+                }
+
+                buffer.clear();
+                outbound::encode_into(response, &mut buffer)
+                    .expect("Writing into a Vec never fails");
+                sender.send(buffer).unwrap();
+            }
+        };
+
+        // Main thread
+        while !self.shutdown.load(Ordering::Acquire) {
+            handle(job_queue.dequeue());
+        }
+
+        log::info!("Shutting down");
+
+        // Acceptors are blocked in accept(), connect to wake them up so they
+        // see the shutdown and close their listeners
+        if let Ok(addr) = listener.local_addr() {
+            for _i in 0..self.num_acceptors {
+                let _ = TcpStream::connect(addr);
             }
         }
+
+        // Commands which are already queued still get their responses
+        while !job_queue.is_empty() {
+            handle(job_queue.dequeue());
+        }
+
+        // The port is only free once every acceptor has dropped its listener
+        for acceptor in acceptors {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+impl Server {
+    /// Same as TcpListener::bind, except the backlog is configurable
+    fn bind(&self) -> io::Result<TcpListener> {
+        let addr = (self.interface.as_str(), self.port as u16)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind"))?;
+
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        // Same as std, so the port can be reused right after a restart
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(self.tcp_backlog)?;
+        Ok(socket.into())
     }
 }
 
-fn accept_loop(listener: TcpListener, job_queue: Queue<CommandWithSender>) {
+/// Keeps track of the number of connected clients across acceptor threads
+#[derive(Clone)]
+struct ClientLimit {
+    connected: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl ClientLimit {
+    fn try_acquire(&self) -> bool {
+        self.connected
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |connected| {
+                if connected < self.max {
+                    Some(connected + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    fn release(&self) {
+        self.connected.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Settings of an acceptor thread
+struct Acceptor {
+    tcp_nodelay: bool,
+    max_arguments: usize,
+    max_consecutive_errors: usize,
+    shutdown: Arc<AtomicBool>,
+    monitors: Arc<AtomicUsize>,
+}
+
+fn accept_loop<Q>(
+    listener: TcpListener,
+    job_queue: Q,
+    client_limit: ClientLimit,
+    acceptor: Acceptor,
+) where
+    Q: JobQueue<CommandWithSender> + Clone + Send + 'static,
+{
     loop {
-        match listener.accept() {
-            Ok((stream, _addr)) => {
+        let result = accept(&listener, acceptor.tcp_nodelay);
+        if acceptor.shutdown.load(Ordering::Acquire) {
+            break;
+        }
+
+        match result {
+            Ok(mut stream) => {
+                if !client_limit.try_acquire() {
+                    let response = outbound::encode(CommandResponse::Error(String::from(
+                        "ERR max number of clients reached",
+                    )));
+                    let _ = stream.write_all(&response);
+                    continue;
+                }
+
                 let cloned_queue = job_queue.clone();
-                thread::spawn(move || handle_connection(stream, cloned_queue));
+                let cloned_limit = client_limit.clone();
+                let max_arguments = acceptor.max_arguments;
+                let max_consecutive_errors = acceptor.max_consecutive_errors;
+                let monitors = acceptor.monitors.clone();
+                thread::spawn(move || {
+                    handle_connection(
+                        stream,
+                        cloned_queue,
+                        max_arguments,
+                        max_consecutive_errors,
+                        monitors,
+                    );
+                    cloned_limit.release();
+                });
             }
             Err(e) => println!("couldn't get client: {e:?}"),
         }
     }
 }
 
-fn handle_connection(stream: TcpStream, job_queue: Queue<CommandWithSender>) {
+fn accept(listener: &TcpListener, tcp_nodelay: bool) -> io::Result<TcpStream> {
+    let (stream, _addr) = listener.accept()?;
+    stream.set_nodelay(tcp_nodelay)?;
+    Ok(stream)
+}
+
+/// A line of the MONITOR stream, e.g. +1339518083.107412 [0 127.0.0.1:60866] "GET" "key"
+fn timestamped(monitor_line: &str) -> Vec<u8> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!(
+        "+{}.{:06} {monitor_line}\r\n",
+        now.as_secs(),
+        now.subsec_micros()
+    )
+    .into_bytes()
+}
+
+// Protocol errors are replied by the connection thread itself, with the same buffer
+fn write_error(stream: &mut TcpStream, buffer: &mut Vec<u8>, reply: String) -> io::Result<()> {
+    buffer.clear();
+    outbound::encode_into(CommandResponse::Error(reply), buffer)?;
+    stream.write_all(buffer)
+}
+
+/// Each connection has at most one command in flight: we don't read the next command
+/// until the response of the current one is written. A client pipelining commands
+/// stalls on its own socket buffer instead of growing the job queue
+fn handle_connection<Q: JobQueue<CommandWithSender>>(
+    stream: TcpStream,
+    job_queue: Q,
+    max_arguments: usize,
+    max_consecutive_errors: usize,
+    monitors: Arc<AtomicUsize>,
+) {
     let mut cloned_stream = stream.try_clone().unwrap();
-    let command_iter = inbound::CommandIter::new(stream);
+    let client = stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_default();
+    let mut command_iter = inbound::CommandIter::new(stream)
+        .max_arguments(max_arguments)
+        .monitors(monitors);
     let start = Instant::now();
+    let mut consecutive_errors = 0;
+    let mut buffer = vec![];
+
+    while let Some(result) = command_iter.next() {
+        let command = match result {
+            Ok(command) => {
+                consecutive_errors = 0;
+                command
+            }
+            // The iteration ends by itself after a protocol error
+            Err(error) => {
+                consecutive_errors += 1;
+                if consecutive_errors > max_consecutive_errors {
+                    log::warn!(
+                        "Closing a connection after {consecutive_errors} invalid commands in a row"
+                    );
+                    let reply = String::from(
+                        "ERR too many invalid commands in a row, closing the connection",
+                    );
+                    let _ = write_error(&mut cloned_stream, &mut buffer, reply);
+                    break;
+                }
+
+                if let Some(reply) = error.reply() {
+                    if write_error(&mut cloned_stream, &mut buffer, reply).is_err() {
+                        break;
+                    }
+                }
+
+                continue;
+            }
+        };
 
-    for command in command_iter {
+        let quit = matches!(command, Command::Quit);
+        let monitor = matches!(command, Command::Monitor);
+        let monitor_line = command_iter
+            .take_monitor_line()
+            .map(|line| format!("[0 {client}] {line}"));
         let (tx, rx): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = mpsc::channel();
 
         let duration = start.elapsed();
         log::debug!("Parse command: {command:?}. Took: {duration:?}");
 
         let start = Instant::now();
-        job_queue.enqueue(CommandWithSender(command, Some(tx)));
+        let reply_to = (tx, mem::take(&mut buffer));
+        job_queue.enqueue(CommandWithSender(command, Some(reply_to), monitor_line));
         let duration = start.elapsed();
         log::debug!("Enqueue took: {duration:?}");
 
         let start = Instant::now();
-        let response = rx.recv().unwrap();
+        buffer = rx.recv().unwrap();
         let duration = start.elapsed();
         log::debug!("Wait for response took: {duration:?}");
 
         let start = Instant::now();
         cloned_stream
-            .write_all(&response)
+            .write_all(&buffer)
             .expect("Fail to write to socket");
 
+        if buffer.capacity() > MAX_KEPT_REPLY_BUFFER {
+            buffer = vec![];
+        }
+
         let duration = start.elapsed();
         log::debug!("Write response took: {:?}", duration);
+
+        if quit {
+            break;
+        }
+
+        // From now on the connection only receives the commands of the other
+        // clients, until the client hangs up or the server shuts down
+        if monitor {
+            for line in rx {
+                if cloned_stream.write_all(&line).is_err() {
+                    break;
+                }
+            }
+
+            break;
+        }
     }
 
     log::debug!("Thread is terminating");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{accept, CommandWithSender, ConfigError, JobQueueKind, Server};
+    use crate::core::Command;
+    use crate::job_queue::{deque, JobQueue};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Records the deepest the queue has ever been with client commands
+    #[derive(Clone)]
+    struct DepthTrackingQueue {
+        queue: deque::Queue<CommandWithSender>,
+        depth: Arc<AtomicUsize>,
+        max_depth: Arc<AtomicUsize>,
+    }
+
+    impl JobQueue<CommandWithSender> for DepthTrackingQueue {
+        fn enqueue(&self, item: CommandWithSender) {
+            if !matches!(item.0, Command::ExpIntervalCheck) {
+                let depth = self.depth.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_depth.fetch_max(depth, Ordering::SeqCst);
+            }
+
+            self.queue.enqueue(item);
+        }
+
+        fn dequeue(&self) -> CommandWithSender {
+            let item = self.queue.dequeue();
+            if !matches!(item.0, Command::ExpIntervalCheck) {
+                self.depth.fetch_sub(1, Ordering::SeqCst);
+            }
+
+            item
+        }
+
+        fn len(&self) -> usize {
+            self.queue.len()
+        }
+    }
+
+    #[test]
+    fn start_with_each_job_queue() {
+        let queues = [
+            (JobQueueKind::Deque, 7880),
+            (JobQueueKind::Channel, 7881),
+            (JobQueueKind::Disruptor, 7882),
+        ];
+
+        for (job_queue, port) in queues {
+            let mut stream = start_server(Server {
+                port,
+                job_queue,
+                ..Default::default()
+            });
+
+            let response = request(
+                &mut stream,
+                b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$3\r\n123\r\n",
+                5,
+            );
+            assert_eq!(response, b"+OK\r\n");
+
+            let response = request(&mut stream, b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n", 9);
+            assert_eq!(response, b"$3\r\n123\r\n");
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let server = Server::builder()
+            .interface("0.0.0.0")
+            .port(6379)
+            .job_queue(JobQueueKind::Channel)
+            .num_acceptors(4)
+            .build()
+            .unwrap();
+
+        assert_eq!(server.interface, "0.0.0.0");
+        assert_eq!(server.port, 6379);
+        assert_eq!(server.job_queue, JobQueueKind::Channel);
+        assert_eq!(server.num_acceptors, 4);
+        assert_eq!(server.max_clients, Server::default().max_clients);
+    }
+
+    #[test]
+    fn builder_validation() {
+        let result = Server::builder().port(0).build();
+        assert!(matches!(result, Err(ConfigError::InvalidPort(0))));
+
+        let result = Server::builder().port(70_000).build();
+        assert!(matches!(result, Err(ConfigError::InvalidPort(70_000))));
+
+        let result = Server::builder().num_acceptors(0).build();
+        assert!(matches!(result, Err(ConfigError::NoAcceptor)));
+
+        let result = Server::builder().daemonize(true).build();
+        if cfg!(feature = "daemon") {
+            assert!(result.unwrap().daemonize);
+        } else {
+            assert!(matches!(result, Err(ConfigError::Unsupported(_))));
+        }
+    }
+
+    #[test]
+    fn tcp_nodelay_and_backlog() {
+        for (tcp_nodelay, port) in [(true, 7885), (false, 7886)] {
+            let server = Server::builder()
+                .port(port)
+                .tcp_nodelay(tcp_nodelay)
+                .tcp_backlog(16)
+                .build()
+                .unwrap();
+            let listener = server.bind().unwrap();
+
+            let _client = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+            let stream = accept(&listener, server.tcp_nodelay).unwrap();
+            assert_eq!(stream.nodelay().unwrap(), tcp_nodelay);
+        }
+    }
+
+    #[test]
+    fn shutdown() {
+        let server = Arc::new(
+            Server::builder()
+                .port(7887)
+                .num_acceptors(2)
+                .build()
+                .unwrap(),
+        );
+        let (tx, rx) = mpsc::channel();
+
+        let server_clone = server.clone();
+        thread::spawn(move || {
+            server_clone.start();
+            tx.send(()).unwrap();
+        });
+
+        let mut stream = connect("127.0.0.1:7887");
+        let response = request(&mut stream, b"*1\r\n$6\r\nDBSIZE\r\n", 4);
+        assert_eq!(response, b":0\r\n");
+
+        server.shutdown();
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        // Listeners are closed
+        assert!(TcpStream::connect("127.0.0.1:7887").is_err());
+    }
+
+    #[test]
+    fn max_arguments() {
+        let server = Server::builder()
+            .port(7888)
+            .max_arguments(100)
+            .build()
+            .unwrap();
+        let mut stream = start_server(server);
+
+        let mut command = b"*101\r\n$3\r\nDEL\r\n".to_vec();
+        for i in 0..100 {
+            let key = format!("key{i}");
+            command.extend_from_slice(format!("${}\r\n{key}\r\n", key.len()).as_bytes());
+        }
+        let response = request(&mut stream, &command, 4);
+        assert_eq!(response, b":0\r\n");
+
+        let mut stream = connect("127.0.0.1:7888");
+        let mut command = b"*102\r\n$3\r\nDEL\r\n".to_vec();
+        for i in 0..101 {
+            let key = format!("key{i}");
+            command.extend_from_slice(format!("${}\r\n{key}\r\n", key.len()).as_bytes());
+        }
+        stream.write_all(&command).unwrap();
+
+        let mut buffer = vec![];
+        stream.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, b"-ERR Protocol error: too many arguments\r\n");
+    }
+
+    #[test]
+    fn max_consecutive_errors() {
+        let server = Server::builder()
+            .port(7892)
+            .max_consecutive_errors(2)
+            .build()
+            .unwrap();
+        let mut stream = start_server(server);
+        let bad = b"*1\r\n$5\r\nBOGUS\r\n";
+        let bad_reply = b"-ERR unknown command 'BOGUS', with args beginning with: \r\n";
+
+        // Errors interleaved with valid commands are tolerated
+        for _ in 0..3 {
+            assert_eq!(request(&mut stream, bad, bad_reply.len()), bad_reply);
+            assert_eq!(request(&mut stream, bad, bad_reply.len()), bad_reply);
+            let response = request(&mut stream, b"*1\r\n$6\r\nDBSIZE\r\n", 4);
+            assert_eq!(response, b":0\r\n");
+        }
+
+        stream.write_all(&bad.repeat(3)).unwrap();
+        let mut buffer = vec![];
+        stream.read_to_end(&mut buffer).unwrap();
+        let mut expected = bad_reply.repeat(2);
+        expected.extend_from_slice(
+            b"-ERR too many invalid commands in a row, closing the connection\r\n",
+        );
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn invalid_command_keeps_connection() {
+        let server = Server::builder().port(7889).build().unwrap();
+        let mut stream = start_server(server);
+
+        let response = request(&mut stream, b"*2\r\n$8\r\nFLUSHALL\r\n$5\r\nASYNC\r\n", 5);
+        assert_eq!(response, b"+OK\r\n");
+
+        let response = request(&mut stream, b"*2\r\n$8\r\nFLUSHALL\r\n$5\r\nBOGUS\r\n", 19);
+        assert_eq!(response, b"-ERR syntax error\r\n");
+
+        let bogus = b"*3\r\n$8\r\nBOGUSCMD\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
+        let reply = b"-ERR unknown command 'BOGUSCMD', with args beginning with: 'foo' 'bar' \r\n";
+        assert_eq!(request(&mut stream, bogus, reply.len()), reply);
+
+        let response = request(&mut stream, b"*1\r\n$6\r\nDBSIZE\r\n", 4);
+        assert_eq!(response, b":0\r\n");
+    }
+
+    #[test]
+    fn replication_commands() {
+        let server = Server::builder().port(7893).build().unwrap();
+        let mut stream = start_server(server);
+        let reply = b"-ERR This instance has replication disabled\r\n";
+
+        let commands: [&[u8]; 3] = [
+            b"*3\r\n$9\r\nREPLICAOF\r\n$2\r\nNO\r\n$3\r\nONE\r\n",
+            b"*3\r\n$7\r\nSLAVEOF\r\n$9\r\nlocalhost\r\n$4\r\n6379\r\n",
+            b"*1\r\n$8\r\nFAILOVER\r\n",
+        ];
+        for command in commands {
+            assert_eq!(request(&mut stream, command, reply.len()), reply);
+        }
+
+        let command = b"*2\r\n$5\r\nDEBUG\r\n$14\r\nCHANGE-REPL-ID\r\n";
+        assert_eq!(request(&mut stream, command, 5), b"+OK\r\n");
+
+        let response = request(&mut stream, b"*1\r\n$6\r\nDBSIZE\r\n", 4);
+        assert_eq!(response, b":0\r\n");
+    }
+
+    #[test]
+    fn info_uptime() {
+        let server = Server::builder().port(7894).build().unwrap();
+        let stream = start_server(server);
+        thread::sleep(Duration::from_millis(100));
+
+        let mut writer = stream.try_clone().unwrap();
+        writer.write_all(b"*1\r\n$4\r\nINFO\r\n").unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let length = header.trim_end().strip_prefix('$').unwrap();
+        let mut info = vec![0; length.parse::<usize>().unwrap()];
+        reader.read_exact(&mut info).unwrap();
+
+        let info = String::from_utf8(info).unwrap();
+        let uptime = info
+            .lines()
+            .find_map(|line| line.strip_prefix("uptime_in_seconds:"))
+            .unwrap();
+        assert!(uptime.parse::<u64>().is_ok());
+    }
+
+    #[test]
+    fn debug_sleep() {
+        let server = Server::builder().port(7895).build().unwrap();
+        let mut sleeping = start_server(server);
+        let start = Instant::now();
+        sleeping
+            .write_all(b"*3\r\n$5\r\nDEBUG\r\n$5\r\nSLEEP\r\n$3\r\n0.5\r\n")
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        // The command of another connection waits for the core to wake up
+        let mut other = TcpStream::connect("127.0.0.1:7895").unwrap();
+        other.write_all(b"*1\r\n$6\r\nDBSIZE\r\n").unwrap();
+        let mut reply = [0; 4];
+        other.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b":0\r\n");
+        assert!(start.elapsed() >= Duration::from_millis(500));
+
+        let mut reply = [0; 5];
+        sleeping.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"+OK\r\n");
+    }
+
+    #[test]
+    fn reply_buffer_reuse() {
+        let server = Server::builder().port(7896).build().unwrap();
+        let stream = start_server(server);
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let value = "a".repeat(100_000);
+
+        let mut request = format!(
+            "*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n${}\r\n{value}\r\n",
+            value.len()
+        );
+        request += "*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n*2\r\n$3\r\nGET\r\n$7\r\nmissing\r\n";
+        writer.write_all(request.as_bytes()).unwrap();
+
+        // Each reply starts from an empty buffer, whatever the previous one left
+        let expected = format!("+OK\r\n${}\r\n{value}\r\n$-1\r\n", value.len());
+        let mut replies = vec![0; expected.len()];
+        reader.read_exact(&mut replies).unwrap();
+        assert_eq!(String::from_utf8(replies).unwrap(), expected);
+    }
+
+    #[test]
+    fn from_config_file() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/redis.conf");
+        let server = Server::from_config_file(path).unwrap();
+
+        assert_eq!(server.interface, "0.0.0.0");
+        assert_eq!(server.port, 6380);
+        assert_eq!(server.max_clients, 128);
+
+        let result = Server::from_config_file("does/not/exist.conf");
+        assert!(matches!(result, Err(ConfigError::IoError(_))));
+    }
+
+    #[test]
+    fn max_clients() {
+        let server = Server::builder().port(7883).max_clients(1).build().unwrap();
+        let mut first = start_server(server);
+
+        let mut second = TcpStream::connect("127.0.0.1:7883").unwrap();
+        let mut buffer = vec![];
+        second.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, b"-ERR max number of clients reached\r\n");
+
+        let response = request(&mut first, b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n", 5);
+        assert_eq!(response, b"$-1\r\n");
+    }
+
+    #[test]
+    fn pipelining_is_bounded() {
+        let max_depth = Arc::new(AtomicUsize::new(0));
+        let queue = DepthTrackingQueue {
+            queue: deque::Queue::new(),
+            depth: Arc::new(AtomicUsize::new(0)),
+            max_depth: max_depth.clone(),
+        };
+
+        let server = Server::builder().port(7884).build().unwrap();
+        thread::spawn(move || server.run(queue, Instant::now()));
+        let mut stream = connect("127.0.0.1:7884");
+
+        // Pipeline all commands at once and only then read the responses
+        let commands = b"*2\r\n$4\r\nINCR\r\n$3\r\nkey\r\n".repeat(1_000);
+        stream.write_all(&commands).unwrap();
+
+        let mut buffer = vec![];
+        while !buffer.ends_with(b":1000\r\n") {
+            let mut chunk = [0; 1024];
+            let len = stream.read(&mut chunk).unwrap();
+            buffer.extend_from_slice(&chunk[..len]);
+        }
+
+        assert_eq!(max_depth.load(Ordering::SeqCst), 1);
+    }
+
+    fn start_server(server: Server) -> TcpStream {
+        let addr = format!("{}:{}", server.interface, server.port);
+        thread::spawn(move || server.start());
+        connect(&addr)
+    }
+
+    fn connect(addr: &str) -> TcpStream {
+        // Wait for the listener to be ready
+        for _i in 1..=50 {
+            if let Ok(stream) = TcpStream::connect(addr) {
+                return stream;
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        panic!("Fail to connect to {addr}");
+    }
+
+    fn request(stream: &mut TcpStream, bytes: &[u8], response_len: usize) -> Vec<u8> {
+        stream.write_all(bytes).unwrap();
+
+        let mut buffer = vec![0; response_len];
+        stream.read_exact(&mut buffer).unwrap();
+        buffer
+    }
+}
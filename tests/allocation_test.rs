@@ -0,0 +1,52 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tiny_redis::connection::inbound::CommandIter;
+
+// Counts every allocation made by this test binary, which holds a single test
+// so nothing else allocates while it measures
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn parse_get_pipeline_allocations(commands: usize) -> usize {
+    let input = "*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n".repeat(commands);
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for command in CommandIter::new(input.as_bytes()) {
+        command.unwrap();
+    }
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn parse_get_pipeline() {
+    let short = parse_get_pipeline_allocations(1_000);
+    let long = parse_get_pipeline_allocations(2_000);
+
+    // Lines and arguments go through buffers reused across commands, so each
+    // GET only allocates its name and its key
+    assert_eq!(
+        long - short,
+        2 * 1_000,
+        "{short} allocations for 1000 GETs, {long} for 2000"
+    );
+}
@@ -1,10 +1,20 @@
 extern crate redis;
-use std::{sync::Once, thread};
+use std::{sync::Once, thread, time::Duration};
 use tiny_redis::server::Server;
 
 pub fn setup() -> redis::Connection {
     initialize();
     let client = redis::Client::open("redis://127.0.0.1:7878/").unwrap();
+
+    // The server may not be listening yet when the first test runs
+    for _ in 0..50 {
+        if let Ok(connection) = client.get_connection() {
+            return connection;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
     client.get_connection().unwrap()
 }
 
@@ -16,6 +26,7 @@ fn initialize() {
             let endpoint = Server {
                 interface: String::from("127.0.0.1"),
                 port: 7878,
+                ..Default::default()
             };
 
             endpoint.start();
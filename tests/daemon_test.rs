@@ -0,0 +1,42 @@
+#![cfg(all(unix, feature = "daemon"))]
+use std::fs;
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn daemonize_and_shutdown() {
+    let pidfile = std::env::temp_dir().join("tiny_redis_daemon_test.pid");
+    let _ = fs::remove_file(&pidfile);
+
+    // The parent process exits as soon as the daemon is forked
+    let status = Command::new(env!("CARGO_BIN_EXE_tiny_redis"))
+        .args(["--port", "7890", "--daemonize", "yes", "--pidfile"])
+        .arg(&pidfile)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert!(wait_until(|| pidfile.exists()));
+    let pid = fs::read_to_string(&pidfile).unwrap().trim().to_owned();
+    assert!(pid.parse::<u32>().is_ok());
+    assert!(wait_until(|| TcpStream::connect("127.0.0.1:7890").is_ok()));
+
+    let status = Command::new("kill").args(["-TERM", &pid]).status().unwrap();
+    assert!(status.success());
+    assert!(wait_until(|| !Path::new(&pidfile).exists()));
+}
+
+fn wait_until<F: Fn() -> bool>(condition: F) -> bool {
+    for _ in 0..50 {
+        if condition() {
+            return true;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    false
+}
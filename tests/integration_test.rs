@@ -4,6 +4,8 @@ extern crate redis;
 use rand::{distributions::Alphanumeric, Rng};
 use redis::Commands;
 use serial_test::serial;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
 use std::num::NonZeroUsize;
 use std::thread;
 use std::time::Duration;
@@ -202,6 +204,153 @@ fn incr_decr() {
     assert!(conn.decr::<String, i32, String>(key, 1).is_err());
 }
 
+#[test]
+#[serial]
+fn empty_strings() {
+    let mut conn = common::setup();
+    let key = random_key();
+
+    set(&mut conn, "", "value");
+    let result: Option<String> = conn.get("").unwrap();
+    assert_string(result, "value");
+
+    set(&mut conn, &key, "");
+    let result: redis::Value = conn.get(&key).unwrap();
+    assert_eq!(result, redis::Value::Data(vec![]));
+
+    let result: isize = conn.strlen(&key).unwrap();
+    assert_eq!(result, 0);
+    let result: isize = conn.del("").unwrap();
+    assert_eq!(result, 1);
+}
+
+// A command and its arguments, {key} stands for the key
+type Query = &'static [&'static str];
+
+// For each type: a command creating a key of that type, then the commands
+// expecting that type
+const COMMANDS_BY_TYPE: &[(&str, Query, &[Query])] = &[
+    (
+        "string",
+        &["SET", "{key}", "value"],
+        &[
+            &["GET", "{key}"],
+            &["GETDEL", "{key}"],
+            &["GETSET", "{key}", "value"],
+            &["APPEND", "{key}", "value"],
+            &["STRLEN", "{key}"],
+            &["GETRANGE", "{key}", "0", "1"],
+            &["SETRANGE", "{key}", "0", "value"],
+            &["INCR", "{key}"],
+            &["DECR", "{key}"],
+            &["INCRBY", "{key}", "1"],
+            &["DECRBY", "{key}", "1"],
+        ],
+    ),
+    (
+        "list",
+        &["RPUSH", "{key}", "value"],
+        &[
+            &["LPUSH", "{key}", "value"],
+            &["RPUSH", "{key}", "value"],
+            &["LPUSHX", "{key}", "value"],
+            &["RPUSHX", "{key}", "value"],
+            &["LPOP", "{key}"],
+            &["RPOP", "{key}"],
+            &["LMPOP", "1", "{key}", "LEFT"],
+        ],
+    ),
+];
+
+#[test]
+#[serial]
+fn wrong_type() {
+    let mut conn = common::setup();
+
+    for (key_type, create, _) in COMMANDS_BY_TYPE {
+        for (command_type, _, commands) in COMMANDS_BY_TYPE {
+            if command_type == key_type {
+                continue;
+            }
+
+            for command in commands.iter() {
+                let key = random_key();
+                let _: () = query(create, &key).query(&mut conn).unwrap();
+
+                let error = query(command, &key)
+                    .query::<redis::Value>(&mut conn)
+                    .unwrap_err();
+                assert_eq!(
+                    (error.kind(), error.code()),
+                    (redis::ErrorKind::ExtensionError, Some("WRONGTYPE")),
+                    "{command:?} on a {key_type}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[serial]
+fn quit() {
+    // The server is started by setup, the connection itself is not needed
+    let _conn = common::setup();
+    let mut stream = TcpStream::connect("127.0.0.1:7878").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    stream.write_all(b"*1\r\n$4\r\nQUIT\r\n").unwrap();
+    let mut buffer = [0; 5];
+    stream.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"+OK\r\n");
+
+    // The server closes its side of the connection after the reply
+    let read = stream.read(&mut buffer).unwrap();
+    assert_eq!(read, 0);
+}
+
+#[test]
+#[serial]
+fn monitor() {
+    let mut conn = common::setup();
+    let key = random_key();
+    let stream = TcpStream::connect("127.0.0.1:7878").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut monitor = BufReader::new(stream);
+
+    monitor
+        .get_mut()
+        .write_all(b"*1\r\n$7\r\nMONITOR\r\n")
+        .unwrap();
+    let mut line = String::new();
+    monitor.read_line(&mut line).unwrap();
+    assert_eq!(line, "+OK\r\n");
+
+    let _: () = conn.set(&key, "value").unwrap();
+
+    // e.g. +1339518083.107412 [0 127.0.0.1:60866] "SET" "key" "value"
+    line.clear();
+    monitor.read_line(&mut line).unwrap();
+    let (timestamp, line) = line.strip_prefix('+').unwrap().split_once(' ').unwrap();
+    assert!(timestamp.parse::<f64>().is_ok(), "{timestamp}");
+    assert!(line.starts_with("[0 127.0.0.1:"), "{line}");
+    assert!(
+        line.ends_with(&format!("] \"SET\" \"{key}\" \"value\"\r\n")),
+        "{line}"
+    );
+}
+
+fn query(command: &[&str], key: &str) -> redis::Cmd {
+    let mut cmd = redis::cmd(command[0]);
+    for argument in &command[1..] {
+        cmd.arg(if *argument == "{key}" { key } else { argument });
+    }
+    cmd
+}
+
 fn set(conn: &mut redis::Connection, key: &str, value: &str) {
     let _result: Option<String> = conn.set(key, value).unwrap();
 }
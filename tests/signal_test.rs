@@ -0,0 +1,50 @@
+#![cfg(all(unix, feature = "signals"))]
+use std::net::TcpStream;
+use std::process::{Child, Command, ExitStatus};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn shutdown_on_sigterm() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_tiny_redis"))
+        .args(["--port", "7891"])
+        .spawn()
+        .unwrap();
+
+    let mut connected = false;
+    for _ in 0..50 {
+        if TcpStream::connect("127.0.0.1:7891").is_ok() {
+            connected = true;
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert!(connected);
+
+    let pid = server.id().to_string();
+    let status = Command::new("kill").args(["-TERM", &pid]).status().unwrap();
+    assert!(status.success());
+
+    // A clean shutdown exits with 0, being killed by the signal doesn't
+    let status = wait_with_timeout(&mut server, Duration::from_secs(5));
+    assert!(status.unwrap().success());
+    assert!(TcpStream::connect("127.0.0.1:7891").is_err());
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let step = Duration::from_millis(100);
+    let mut waited = Duration::ZERO;
+
+    while waited < timeout {
+        if let Some(status) = child.try_wait().unwrap() {
+            return Some(status);
+        }
+
+        thread::sleep(step);
+        waited += step;
+    }
+
+    let _ = child.kill();
+    None
+}